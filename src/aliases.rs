@@ -0,0 +1,137 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::error::WeatherError;
+
+/// Short names that stand for a full city query, e.g. `home` for `Seattle,WA,US`.
+///
+/// Names are matched case-insensitively. The aliases live in `aliases.toml` in the
+/// `city_weather` directory of the platform config dir, as `name = "query"` pairs.
+#[derive(Default)]
+pub struct Aliases {
+    path: PathBuf,
+    entries: BTreeMap<String, String>,
+}
+
+impl Aliases {
+    /// Loads the aliases from the default location; a missing file means no aliases.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let path = dirs_next::config_dir()
+            .ok_or_else(|| WeatherError::Config {
+                message: "Could not determine the config directory for storing aliases.".to_owned(),
+                source: None,
+            })?
+            .join("city_weather")
+            .join("aliases.toml");
+
+        Self::load_from(path)
+    }
+
+    pub fn load_from(path: impl Into<PathBuf>) -> Result<Self, Box<dyn Error>> {
+        let path = path.into();
+        let entries = match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content).map_err(|error| {
+                WeatherError::config(format!("Failed to parse {}.", path.display()), error)
+            })?,
+            Err(error) if error.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => {
+                return Err(WeatherError::config(
+                    format!("Failed to read {}.", path.display()),
+                    error,
+                )
+                .into())
+            }
+        };
+
+        Ok(Aliases { path, entries })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The query `city` stands for, or `city` itself when it is not an alias.
+    pub fn resolve<'a>(&'a self, city: &'a str) -> &'a str {
+        self.entries
+            .get(&city.trim().to_lowercase())
+            .map_or(city, String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(name, city)| (name.as_str(), city.as_str()))
+    }
+
+    pub fn add(&mut self, name: &str, city: &str) {
+        self.entries
+            .insert(name.trim().to_lowercase(), city.trim().to_owned());
+    }
+
+    /// Removes the alias, returning whether it existed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.entries.remove(&name.trim().to_lowercase()).is_some()
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        Ok(fs::write(&self.path, toml::to_string(&self.entries)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::Aliases;
+
+    #[test]
+    fn aliases_round_trip() {
+        let path = env::temp_dir()
+            .join(format!("city_weather_aliases_{}", process::id()))
+            .join("aliases.toml");
+
+        let mut aliases = Aliases::load_from(&path).unwrap();
+        assert_eq!(aliases.resolve("home"), "home");
+
+        aliases.add("Home", "Seattle,WA,US");
+        aliases.add("work", "Redmond,WA,US");
+        aliases.save().unwrap();
+
+        let mut aliases = Aliases::load_from(&path).unwrap();
+        assert_eq!(aliases.resolve("home"), "Seattle,WA,US");
+        assert_eq!(aliases.resolve(" HOME "), "Seattle,WA,US");
+        assert_eq!(aliases.resolve("London"), "London");
+        assert!(aliases.remove("work"));
+        assert!(!aliases.remove("work"));
+        assert_eq!(
+            aliases.iter().collect::<Vec<_>>(),
+            [("home", "Seattle,WA,US")]
+        );
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn parse_errors_name_the_file() {
+        let path = env::temp_dir().join(format!("city_weather_bad_aliases_{}.toml", process::id()));
+        fs::write(&path, "home = \n").unwrap();
+
+        let error = Aliases::load_from(&path).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!("Failed to parse {}.", path.display())
+        );
+        assert!(error.source().is_some());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,22 +1,118 @@
-use std::{error::Error, fs, io::Write, path::Path, process};
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    fs::{self, File},
+    future::Future,
+    io::{self, BufWriter, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    sync::Once,
+    thread,
+};
 
 use clap::ValueEnum;
 use getset::Getters;
+use itertools::Itertools;
 use prettytable::{
     format::{self},
     row, Table,
 };
-use serde::Deserialize;
-use tracing::Level;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use terminal_size::{terminal_size, Width};
+use tokio::{signal, sync::mpsc};
+use tracing::{warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
-use crate::weather_client::{self, CityWeather, Client, Config, Connected};
+use crate::{
+    aliases::Aliases,
+    cache,
+    error::WeatherError,
+    utils,
+    weather_client::{
+        self, CityForecast, CityLocation, CityWeather, Client, Config, Connected, Units,
+        ATTRIBUTION,
+    },
+};
 
 #[derive(Debug, Deserialize, Clone, Copy, ValueEnum)]
 pub enum OutputType {
     Table,
     Simple,
     Json,
+    /// Tab-separated values, one result per row.
+    Tsv,
+    /// Comma-separated values, one result per row.
+    Csv,
+    /// Render the results with the template given by `--template-file`.
+    #[cfg(feature = "templates")]
+    Template,
+}
+
+impl OutputType {
+    /// The field separator of the delimited output types.
+    fn delimiter(self) -> u8 {
+        match self {
+            OutputType::Csv => b',',
+            _ => b'\t',
+        }
+    }
+}
+
+/// Where spaces go between a temperature, the degree sign and the unit letter.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum TemperatureSpacing {
+    /// `12°C`
+    #[default]
+    Compact,
+    /// `12 °C`
+    BeforeDegree,
+    /// `12° C`
+    BeforeUnit,
+}
+
+impl TemperatureSpacing {
+    fn format(self, value: i16, units: Units) -> String {
+        let (degree, unit) = (units.degree_sign(), units.temperature_symbol());
+
+        match self {
+            TemperatureSpacing::Compact => format!("{}{}{}", value, degree, unit),
+            TemperatureSpacing::BeforeDegree => format!("{} {}{}", value, degree, unit),
+            TemperatureSpacing::BeforeUnit => format!("{}{} {}", value, degree, unit),
+        }
+    }
+}
+
+/// What results are ordered by, instead of the order the locations were resolved in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    Country,
+    State,
+    City,
+    /// Compared in Celsius, so that results fetched in different units sort together.
+    Temperature,
+}
+
+/// When the table and simple outputs use ANSI colors.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Only when stdout is a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
 }
 
 #[derive(Deserialize, Getters)]
@@ -27,109 +123,2185 @@ pub struct AppConfig {
     #[getset(get = "pub")]
     output: OutputType,
 
+    /// Where temperatures get spaces, unless `--temperature-spacing` is given.
+    #[serde(default)]
+    #[getset(get = "pub")]
+    temperature_spacing: TemperatureSpacing,
+
     #[getset(get = "pub")]
     level: Option<String>,
 }
 
+/// The environment variable that provides the API key, taking precedence over the
+/// `api_key` in the config file.
+pub const API_KEY_VARIABLE: &str = "OPENWEATHER_API_KEY";
+
 impl AppConfig {
-    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
-        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    /// The config file used without `--config`: `config.toml` in the working directory
+    /// when there is one, otherwise the one in the `city_weather` directory of the
+    /// platform config dir (`$XDG_CONFIG_HOME` on Linux).
+    pub fn default_path() -> PathBuf {
+        let local = PathBuf::from("config.toml");
+
+        match dirs_next::config_dir() {
+            Some(dir) if !local.exists() => dir.join("city_weather").join("config.toml"),
+            _ => local,
+        }
+    }
+
+    /// Loads the config at `path`, with the API key from the environment taking
+    /// precedence over the one in the file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WeatherError> {
+        Self::load_with_env_key(path, env::var(API_KEY_VARIABLE).ok())
+    }
+
+    /// Like [`Self::load`], with `env_key` standing in for the environment variable.
+    pub fn load_with_env_key(
+        path: impl AsRef<Path>,
+        env_key: Option<String>,
+    ) -> Result<Self, WeatherError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|error| {
+            WeatherError::config(format!("Failed to read {}.", path.display()), error)
+        })?;
+        let mut config: AppConfig = toml::from_str(&content).map_err(|error| {
+            WeatherError::config(format!("Failed to parse {}.", path.display()), error)
+        })?;
+
+        if let Some(api_key) = env_key.filter(|key| !key.is_empty()) {
+            config.client.set_api_key(api_key);
+        }
+
+        if config.client.api_key().is_empty() {
+            return Err(WeatherError::Config {
+                message: format!(
+                    "No API key configured. Set `api_key` under [client] in the config file or the {} environment variable.",
+                    API_KEY_VARIABLE
+                ),
+                source: None,
+            });
+        }
+
+        Ok(config)
+    }
+}
+
+/// Controls how results are rendered.
+pub struct PrintOptions {
+    pub output_type: OutputType,
+
+    /// Credit the weather data provider after the results, or as a field in JSON output.
+    pub attribution: bool,
+
+    /// Decimal places shown for coordinates in human-readable output; JSON, CSV and TSV
+    /// always carry full precision.
+    pub coordinate_precision: usize,
+
+    /// Serialize each JSON result as a single-level object, see [`flatten_json`].
+    pub json_flat: bool,
+
+    /// Emit temperatures as bare numbers instead of `{ "value": .., "unit": .. }`
+    /// objects, as JSON output did before temperatures carried their unit.
+    pub json_legacy: bool,
+
+    /// Keep the table output even when it is wider than the terminal.
+    pub force_table: bool,
+
+    /// Omit the header row from delimited (TSV and CSV) output.
+    pub no_header: bool,
+
+    /// Show two-letter US state codes as full state names in human-readable output.
+    pub expand_abbreviations: bool,
+
+    /// Show the daily forecast of a city instead of its current weather.
+    pub forecast: bool,
+
+    /// Print only the aggregates of a result set, see [`WeatherSummary`].
+    pub summary_only: bool,
+
+    /// The spacing of temperatures in human-readable output.
+    pub temperature_spacing: TemperatureSpacing,
+
+    /// Bold city names and temperatures colored by how warm they are in the table and
+    /// simple outputs; the other formats are never colored.
+    pub color: bool,
+
+    /// Order the results by this key, ascending unless `descending` is set.
+    pub sort: Option<SortKey>,
+    pub descending: bool,
+
+    /// Write the results to this file instead of stdout. The file stays open, so the
+    /// results of successive interactive queries are appended to it.
+    pub output_file: Option<File>,
+
+    /// The Handlebars template source used by [`OutputType::Template`].
+    #[cfg(feature = "templates")]
+    pub template: Option<String>,
+}
+
+impl PrintOptions {
+    /// The default options for `output_type`, as the command line uses them without
+    /// any other flags.
+    pub fn new(output_type: OutputType) -> Self {
+        PrintOptions {
+            output_type,
+            attribution: false,
+            coordinate_precision: 4,
+            json_flat: false,
+            json_legacy: false,
+            force_table: false,
+            no_header: false,
+            expand_abbreviations: false,
+            forecast: false,
+            summary_only: false,
+            temperature_spacing: TemperatureSpacing::default(),
+            color: false,
+            sort: None,
+            descending: false,
+            output_file: None,
+            #[cfg(feature = "templates")]
+            template: None,
+        }
+    }
+}
+
+/// Reads a Handlebars template from `path`, reporting syntax errors with their
+/// line and column before any request is made.
+#[cfg(feature = "templates")]
+pub fn load_template(path: impl AsRef<Path>) -> Result<String, Box<dyn Error>> {
+    let template = fs::read_to_string(&path)?;
+    handlebars::Handlebars::new()
+        .register_template_string(&path.as_ref().display().to_string(), &template)?;
+
+    Ok(template)
+}
+
+/// Cities queried during an interactive session, in the order they were first asked
+/// for, with the temperature of their first result from the latest lookup.
+#[derive(Default)]
+struct SessionHistory {
+    entries: Vec<(String, Option<String>)>,
+}
+
+impl SessionHistory {
+    fn record(&mut self, city: &str, last_reading: Option<String>) {
+        match self
+            .entries
+            .iter_mut()
+            .find(|(known, _)| known.eq_ignore_ascii_case(city))
+        {
+            Some(entry) => entry.1 = last_reading,
+            None => self.entries.push((city.to_owned(), last_reading)),
+        }
+    }
+
+    /// The city at the 1-based `position`, as listed by `:cities`.
+    fn get(&self, position: usize) -> Option<&str> {
+        position
+            .checked_sub(1)
+            .and_then(|index| self.entries.get(index))
+            .map(|(city, _)| city.as_str())
+    }
+
+    fn print(&self) {
+        if self.entries.is_empty() {
+            println!("No cities queried yet.");
+        }
+
+        for (position, (city, last_reading)) in (1..).zip(&self.entries) {
+            println!(
+                ":{} {} {}",
+                position,
+                city,
+                last_reading.as_deref().unwrap_or("(no results)")
+            );
+        }
+        println!();
     }
 }
 
+/// Prompts for cities until stdin ends (Ctrl-D or the end of piped input), Ctrl-C is
+/// pressed at the prompt, or `quit` / `exit` is entered. Besides city names, `:cities`
+/// (or `:history`) lists the cities queried in this session and `:N` repeats the N-th.
+///
+/// Ctrl-C while a lookup is running cancels just that lookup and drops its in-flight
+/// requests.
 pub async fn print_city_weather_interactive(
     client: &weather_client::Client<Connected>,
-    output_type: &OutputType,
+    aliases: &Aliases,
+    options: &PrintOptions,
 ) {
-    let mut city = String::new();
+    let mut lines = stdin_lines();
+    let mut history = SessionHistory::default();
 
     loop {
-        city.clear();
+        // The prompt goes to stderr so that redirected stdout only contains results.
+        if std::io::stdin().is_terminal() {
+            eprint!("Enter the city name: ");
+            std::io::stderr().flush().expect("failed to flush stderr");
+        }
 
-        print!("Enter the city name: ");
-        std::io::stdout().flush().expect("failed to flush stdout");
-        std::io::stdin()
-            .read_line(&mut city)
-            .expect("failed to read line");
+        let line = tokio::select! {
+            line = lines.recv() => line.transpose().expect("failed to read line"),
+            _ = signal::ctrl_c() => return,
+        };
+
+        let Some(city) = line else {
+            return;
+        };
+        let input = city.trim();
 
-        if city.trim().is_empty() {
+        if input.is_empty() {
             continue;
         }
 
-        if let Err(error) = print_city_weather(client, &city, output_type).await {
-            eprintln!("{}", error);
-            process::exit(1);
+        let query = match input {
+            "quit" | "exit" | ":quit" | ":exit" => return,
+            ":cities" | ":history" => {
+                history.print();
+                continue;
+            }
+            _ => match input.strip_prefix(':').map(str::parse::<usize>) {
+                Some(Ok(position)) => match history.get(position) {
+                    Some(city) => city.to_owned(),
+                    None => {
+                        eprintln!("There is no city :{} in this session.", position);
+                        continue;
+                    }
+                },
+                Some(Err(_)) => {
+                    eprintln!("Unknown command \"{}\", try :cities, :N or :quit.", input);
+                    continue;
+                }
+                None => aliases.resolve(input).to_owned(),
+            },
+        };
+
+        let result = tokio::select! {
+            result = fetch_and_print_city(client, &query, options) => result,
+            _ = signal::ctrl_c() => {
+                eprintln!("Lookup cancelled.");
+                continue;
+            }
+        };
+
+        // A failed lookup is reported and the session goes on, so that a typo or a
+        // network blip does not end it; only a closed stdout does.
+        match result {
+            Ok(temperature) => history.record(&query, temperature),
+            Err(error) if is_broken_pipe(error.as_ref()) => return,
+            Err(error) => eprintln!("Failed to get the weather for \"{}\": {}", query, error),
         }
     }
 }
 
+/// Lines of stdin, read on a thread of their own: a blocking read cannot be cancelled,
+/// so reading on a runtime thread would keep the runtime from shutting down after
+/// Ctrl-C until the next line arrives. The thread ends with stdin or the receiver.
+fn stdin_lines() -> mpsc::UnboundedReceiver<io::Result<String>> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    thread::spawn(move || {
+        for line in io::stdin().lines() {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
 pub async fn print_city_weather(
     app: &Client<Connected>,
     city: &str,
-    output_type: &OutputType,
+    options: &PrintOptions,
 ) -> Result<(), Box<dyn Error>> {
-    let weathers = app.get_weather(city.trim()).await?;
+    fetch_and_print_city(app, city, options).await?;
+
+    Ok(())
+}
+
+/// Prints the forecast or the current weather of `city`, as `options` ask, and returns
+/// the first current temperature for the session history.
+async fn fetch_and_print_city(
+    app: &Client<Connected>,
+    city: &str,
+    options: &PrintOptions,
+) -> Result<Option<String>, Box<dyn Error>> {
+    if options.forecast {
+        let forecasts = app.get_forecast(city.trim()).await?;
+        print_results(city, forecasts.is_empty(), options, |out| {
+            write_forecasts(out, &forecasts, options)
+        })?;
+
+        return Ok(None);
+    }
+
+    let weathers = fetch_and_print_city_weather(app, city, options).await?;
+
+    Ok(weathers
+        .first()
+        .map(|weather| format_temperature(weather, options)))
+}
+
+async fn fetch_and_print_city_weather(
+    app: &Client<Connected>,
+    city: &str,
+    options: &PrintOptions,
+) -> Result<Vec<CityWeather>, Box<dyn Error>> {
+    let mut weathers = app.get_weather(city.trim()).await?;
+    sort_weathers(&mut weathers, options);
+    print_results(city, weathers.is_empty(), options, |out| {
+        write_weathers(out, &weathers, options)
+    })?;
+
+    Ok(weathers)
+}
 
-    if !weathers.is_empty() {
-        match output_type {
-            OutputType::Table => print_weathers_table(weathers),
-            OutputType::Simple => print_weathers_simple(weathers),
-            OutputType::Json => print_weathers_json(weathers),
+/// Orders `weathers` by the sort key of `options`, if any. The sort is stable, so ties
+/// keep their resolved order.
+fn sort_weathers(weathers: &mut [CityWeather], options: &PrintOptions) {
+    let Some(key) = options.sort else {
+        return;
+    };
+
+    weathers.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Country => a.country().cmp(b.country()),
+            SortKey::State => a.state().cmp(b.state()),
+            SortKey::City => a.city_name().cmp(b.city_name()),
+            SortKey::Temperature => celsius(a).total_cmp(&celsius(b)),
         };
+
+        if options.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+/// Prints a result set with `write`, or reports on stderr that `query` matched nothing.
+/// JSON output still gets its empty array, so that piped output stays machine-readable.
+fn print_results(
+    query: &str,
+    is_empty: bool,
+    options: &PrintOptions,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    if is_empty {
+        eprintln!("No results found for \"{}\".", query.trim());
+    }
+
+    print_unless_empty(is_empty, options, write)
+}
+
+/// Prints the results unless there are none; JSON still prints the empty array, so
+/// that scripts always get a document to parse.
+fn print_unless_empty(
+    is_empty: bool,
+    options: &PrintOptions,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    if is_empty && !matches!(options.output_type, OutputType::Json) {
+        return Ok(());
+    }
+
+    print_with(options, write)
+}
+
+/// Renders the weathers the way they are printed, for embedding the output in
+/// another frontend or capturing it in tests.
+///
+/// Unlike printing, a table is kept however wide it is: the output is not going to
+/// the terminal, so its width says nothing about where the output ends up.
+pub fn render_weathers(
+    weathers: &[CityWeather],
+    options: &PrintOptions,
+) -> Result<String, Box<dyn Error>> {
+    let mut out = Vec::new();
+    write_weathers_to(&mut out, weathers, options, false)?;
+
+    Ok(String::from_utf8(out)?)
+}
+
+fn write_weathers(
+    out: &mut dyn Write,
+    weathers: &[CityWeather],
+    options: &PrintOptions,
+) -> Result<(), Box<dyn Error>> {
+    write_weathers_to(out, weathers, options, true)
+}
+
+/// Writes the weathers, falling back to the simple output for a table that does not
+/// fit the terminal if `fit_terminal` is set.
+fn write_weathers_to(
+    out: &mut dyn Write,
+    weathers: &[CityWeather],
+    options: &PrintOptions,
+    fit_terminal: bool,
+) -> Result<(), Box<dyn Error>> {
+    if options.summary_only {
+        return write_summary(out, &WeatherSummary::new(weathers), options);
     }
 
+    match options.output_type {
+        OutputType::Table => {
+            let table = weathers_table(weathers, options);
+
+            if fit_terminal && !table_fits_terminal(&table, options) {
+                write_weathers_simple(out, weathers, options)?;
+            } else if options.color {
+                writeln!(out, "{}", paint_weathers_table(&table, weathers, options))?;
+            } else {
+                write_table(out, &table)?;
+            }
+            write_attribution(out, options)?;
+        }
+        OutputType::Simple => {
+            write_weathers_simple(out, weathers, options)?;
+            write_attribution(out, options)?;
+        }
+        OutputType::Json => write_json(out, weathers, options)?,
+        OutputType::Tsv | OutputType::Csv => write_delimited(
+            out,
+            &WEATHER_COLUMNS,
+            render_rows(weathers, weather_record).into_iter(),
+            options.output_type.delimiter(),
+            options,
+        )?,
+        #[cfg(feature = "templates")]
+        OutputType::Template => write_template(out, &weathers, options)?,
+    };
+
     Ok(())
 }
 
-fn print_weathers_simple(weathers: Vec<CityWeather>) {
-    for weather in weathers {
-        println!(
-            "{} ({}, {}): {}, {}°",
-            weather.city_name(),
-            weather.country(),
-            weather.state().as_deref().unwrap_or(""),
-            weather.weather(),
-            *weather.temperature() as i16
+/// Aggregates over a result set, printed instead of the rows by `--summary-only`.
+#[derive(Serialize)]
+struct WeatherSummary {
+    count: usize,
+
+    /// The units of the temperatures below: those of all results, or metric when they
+    /// were fetched in different units, as with `--units auto`. Absent without results.
+    units: Option<Units>,
+    min_temperature: Option<f64>,
+    max_temperature: Option<f64>,
+    average_temperature: Option<f64>,
+
+    /// The number of results per weather condition.
+    conditions: BTreeMap<String, usize>,
+}
+
+impl WeatherSummary {
+    fn new(weathers: &[CityWeather]) -> Self {
+        let units = match weathers
+            .iter()
+            .map(|weather| *weather.units())
+            .all_equal_value()
+        {
+            Ok(units) => Some(units),
+            Err(Some(_)) => Some(Units::Metric),
+            Err(None) => None,
+        };
+        let temperatures = weathers.iter().map(|weather| match Some(*weather.units()) {
+            same if same == units => *weather.temperature(),
+            _ => celsius(weather),
+        });
+        let (min, max, sum) = temperatures.fold(
+            (None, None, 0.0),
+            |(min, max, sum): (Option<f64>, Option<f64>, f64), temperature| {
+                (
+                    Some(min.map_or(temperature, |min| min.min(temperature))),
+                    Some(max.map_or(temperature, |max| max.max(temperature))),
+                    sum + temperature,
+                )
+            },
         );
+
+        WeatherSummary {
+            count: weathers.len(),
+            units,
+            min_temperature: min,
+            max_temperature: max,
+            average_temperature: min.map(|_| sum / weathers.len() as f64),
+            conditions: weathers.iter().map(CityWeather::weather).fold(
+                BTreeMap::new(),
+                |mut conditions, condition| {
+                    *conditions.entry(condition.clone()).or_default() += 1;
+                    conditions
+                },
+            ),
+        }
     }
-    println!();
 }
 
-fn print_weathers_table(weathers: Vec<CityWeather>) {
+fn write_summary(
+    out: &mut dyn Write,
+    summary: &WeatherSummary,
+    options: &PrintOptions,
+) -> Result<(), Box<dyn Error>> {
+    match options.output_type {
+        OutputType::Json => write_json(out, summary, options)?,
+        #[cfg(feature = "templates")]
+        OutputType::Template => write_template(out, summary, options)?,
+        _ => {
+            write!(out, "{} results", summary.count)?;
+            if let (Some(units), Some(min), Some(max), Some(average)) = (
+                summary.units,
+                summary.min_temperature,
+                summary.max_temperature,
+                summary.average_temperature,
+            ) {
+                let format = |value: f64| format_degrees(value, units, options);
+                write!(
+                    out,
+                    ": min {}, max {}, avg {}",
+                    format(min),
+                    format(max),
+                    format(average)
+                )?;
+            }
+            writeln!(out)?;
+
+            let conditions = summary
+                .conditions
+                .iter()
+                .map(|(condition, count)| format!("{} {}", condition, count))
+                .join(", ");
+            writeln!(out, "Conditions: {}", conditions)?;
+            writeln!(out)?;
+            write_attribution(out, options)?;
+        }
+    };
+
+    Ok(())
+}
+
+fn write_forecasts(
+    out: &mut dyn Write,
+    forecasts: &[CityForecast],
+    options: &PrintOptions,
+) -> Result<(), Box<dyn Error>> {
+    match options.output_type {
+        OutputType::Table => {
+            let table = forecasts_table(forecasts, options);
+
+            if table_fits_terminal(&table, options) {
+                write_table(out, &table)?;
+            } else {
+                write_forecasts_simple(out, forecasts, options)?;
+            }
+            write_attribution(out, options)?;
+        }
+        OutputType::Simple => {
+            write_forecasts_simple(out, forecasts, options)?;
+            write_attribution(out, options)?;
+        }
+        OutputType::Json => write_json(out, forecasts, options)?,
+        OutputType::Tsv | OutputType::Csv => write_delimited(
+            out,
+            &FORECAST_COLUMNS,
+            forecasts.iter().flat_map(forecast_records),
+            options.output_type.delimiter(),
+            options,
+        )?,
+        #[cfg(feature = "templates")]
+        OutputType::Template => write_template(out, &forecasts, options)?,
+    };
+
+    Ok(())
+}
+
+pub async fn print_weather_at(
+    app: &Client<Connected>,
+    lat: f64,
+    lon: f64,
+    options: &PrintOptions,
+) -> Result<(), Box<dyn Error>> {
+    let query = format!("{},{}", lat, lon);
+
+    if options.forecast {
+        let forecasts = [app.get_forecast_at(lat, lon).await?];
+
+        return print_results(&query, false, options, |out| {
+            write_forecasts(out, &forecasts, options)
+        });
+    }
+
+    let weathers: Vec<CityWeather> = app.get_weather_at(lat, lon).await?.into_iter().collect();
+
+    print_results(&query, weathers.is_empty(), options, |out| {
+        write_weathers(out, &weathers, options)
+    })
+}
+
+pub async fn print_zip_weather(
+    app: &Client<Connected>,
+    zip: &str,
+    options: &PrintOptions,
+) -> Result<(), Box<dyn Error>> {
+    if options.forecast {
+        let forecasts: Vec<_> = app.get_forecast_by_zip(zip).await?.into_iter().collect();
+
+        print_results(zip, forecasts.is_empty(), options, |out| {
+            write_forecasts(out, &forecasts, options)
+        })
+    } else {
+        let weathers: Vec<_> = app.get_weather_by_zip(zip).await?.into_iter().collect();
+
+        print_results(zip, weathers.is_empty(), options, |out| {
+            write_weathers(out, &weathers, options)
+        })
+    }
+}
+
+/// Checks that `lat` and `lon` are a valid latitude and longitude.
+pub fn check_coordinates(lat: f64, lon: f64) -> Result<(f64, f64), Box<dyn Error>> {
+    if !(-90.0..=90.0).contains(&lat) {
+        return Err(format!("Invalid latitude {}: it must be between -90 and 90.", lat).into());
+    }
+
+    if !(-180.0..=180.0).contains(&lon) {
+        return Err(format!(
+            "Invalid longitude {}: it must be between -180 and 180.",
+            lon
+        )
+        .into());
+    }
+
+    Ok((lat, lon))
+}
+
+/// Parses coordinates given as `LAT,LON`, e.g. `51.5,-0.12`.
+pub fn parse_coordinates(coordinates: &str) -> Result<(f64, f64), Box<dyn Error>> {
+    let invalid = || format!("Invalid coordinates \"{}\", expected LAT,LON.", coordinates);
+
+    let (lat, lon) = coordinates.split_once(',').ok_or_else(invalid)?;
+    let lat = lat.trim().parse().map_err(|_| invalid())?;
+    let lon = lon.trim().parse().map_err(|_| invalid())?;
+
+    check_coordinates(lat, lon)
+}
+
+/// Decodes a full Plus Code (Open Location Code), e.g. `9C3XGV4C+XV`, into the
+/// latitude and longitude of its center.
+#[cfg(feature = "pluscode")]
+pub fn decode_plus_code(code: &str) -> Result<(f64, f64), Box<dyn Error>> {
+    let area = open_location_code::decode(code.trim())
+        .map_err(|error| format!("Invalid plus code \"{}\": {}", code.trim(), error))?;
+
+    Ok((area.center.lat(), area.center.lng()))
+}
+
+/// Buffers the whole output so that large result sets are written with a single
+/// lock and flush of stdout instead of one per line.
+fn stdout_writer() -> BufWriter<io::StdoutLock<'static>> {
+    BufWriter::new(io::stdout().lock())
+}
+
+/// Runs `write` against the output file of `options`, or [`stdout_writer`] without
+/// one, and flushes what it wrote.
+fn print_with(
+    options: &PrintOptions,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut out: Box<dyn Write + '_> = match &options.output_file {
+        Some(file) => Box::new(BufWriter::new(file)),
+        None => Box::new(stdout_writer()),
+    };
+    write(&mut out)?;
+
+    Ok(out.flush()?)
+}
+
+/// Prints the count, total, min, max and average duration of every kind of request
+/// made so far to stderr, keeping it apart from the results, followed by the cache
+/// hit ratio so that the TTL can be tuned.
+pub fn print_timings() {
+    for summary in utils::timing_summaries() {
+        eprintln!(
+            "{}: {} requests, total {}ms, min {}ms, max {}ms, avg {}ms",
+            summary.identifier,
+            summary.count,
+            summary.total.as_millis(),
+            summary.min.as_millis(),
+            summary.max.as_millis(),
+            summary.average().as_millis()
+        );
+    }
+
+    let (hits, misses) = cache::cache_stats();
+    if let Some(percent) = (hits * 100).checked_div(hits + misses) {
+        eprintln!("cache: {} hits, {} misses ({}%)", hits, misses, percent);
+    }
+}
+
+/// Reads one city name per line from `path`, or from stdin when `path` is `-`.
+/// Blank lines are skipped. At most `max` cities are returned, so that a huge input
+/// cannot burn through the API quota by accident; the rest is dropped with a warning.
+pub fn read_cities(path: impl AsRef<Path>, max: Option<usize>) -> io::Result<Vec<String>> {
+    let content = if path.as_ref() == Path::new("-") {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content)?;
+        content
+    } else {
+        fs::read_to_string(path)?
+    };
+
+    let mut cities: Vec<String> = content
+        .lines()
+        .map(str::trim)
+        .filter(|city| !city.is_empty())
+        .map(str::to_owned)
+        .collect();
+
+    if let Some(max) = max.filter(|max| cities.len() > *max) {
+        warn!(
+            "only the first {} of {} cities are processed, see --max-cities-from-file and --no-limit",
+            max,
+            cities.len()
+        );
+        cities.truncate(max);
+    }
+
+    Ok(cities)
+}
+
+/// Fetches the weather, or the forecast with `--forecast`, for every city and renders
+/// all results as a single output, so that e.g. JSON output stays one parseable
+/// document. Cities that fail are reported and skipped, and the cities without results
+/// are listed on stderr.
+pub async fn print_cities_weather(
+    app: &Client<Connected>,
+    cities: &[String],
+    options: &PrintOptions,
+) -> Result<(), Box<dyn Error>> {
+    let without_results = if options.forecast {
+        let (forecasts, without_results) =
+            fetch_cities(cities, |city| app.get_forecast(city)).await;
+
+        print_unless_empty(forecasts.is_empty(), options, |out| {
+            write_forecasts(out, &forecasts, options)
+        })?;
+
+        without_results
+    } else {
+        let (mut weathers, without_results) =
+            fetch_cities(cities, |city| app.get_weather(city)).await;
+        sort_weathers(&mut weathers, options);
+
+        print_unless_empty(weathers.is_empty(), options, |out| {
+            write_weathers(out, &weathers, options)
+        })?;
+
+        without_results
+    };
+
+    if !without_results.is_empty() {
+        eprintln!("No results for: {}", without_results.join(", "));
+    }
+
+    Ok(())
+}
+
+/// Runs `fetch` for every city in turn, returning all results and the cities that had
+/// none or failed.
+async fn fetch_cities<'a, T, F, Fut>(cities: &'a [String], fetch: F) -> (Vec<T>, Vec<&'a str>)
+where
+    F: Fn(&'a str) -> Fut,
+    Fut: Future<Output = Result<Vec<T>, WeatherError>>,
+{
+    let mut results = Vec::new();
+    let mut without_results = Vec::new();
+
+    for city in cities {
+        match fetch(city.trim()).await {
+            Ok(city_results) if city_results.is_empty() => without_results.push(city.trim()),
+            Ok(city_results) => results.extend(city_results),
+            Err(error) => {
+                warn!("failed to get weather for {}: {}", city, error);
+                without_results.push(city.trim());
+            }
+        }
+    }
+
+    (results, without_results)
+}
+
+pub async fn print_city_locations(
+    app: &Client<Connected>,
+    city: &str,
+    options: &PrintOptions,
+) -> Result<(), Box<dyn Error>> {
+    let locations = app.get_locations(city.trim()).await?;
+
+    print_results(city, locations.is_empty(), options, |out| {
+        write_locations(out, &locations, options)
+    })
+}
+
+fn write_locations(
+    out: &mut dyn Write,
+    locations: &[CityLocation],
+    options: &PrintOptions,
+) -> Result<(), Box<dyn Error>> {
+    match options.output_type {
+        OutputType::Table => {
+            let table = locations_table(locations, options);
+
+            if table_fits_terminal(&table, options) {
+                write_table(out, &table)?;
+            } else {
+                write_locations_simple(out, locations, options)?;
+            }
+            write_attribution(out, options)?;
+        }
+        OutputType::Simple => {
+            write_locations_simple(out, locations, options)?;
+            write_attribution(out, options)?;
+        }
+        OutputType::Json => write_json(out, locations, options)?,
+        OutputType::Tsv | OutputType::Csv => write_delimited(
+            out,
+            &LOCATION_COLUMNS,
+            locations.iter().map(location_record),
+            options.output_type.delimiter(),
+            options,
+        )?,
+        #[cfg(feature = "templates")]
+        OutputType::Template => write_template(out, &locations, options)?,
+    };
+
+    Ok(())
+}
+
+fn write_locations_simple(
+    out: &mut dyn Write,
+    locations: &[CityLocation],
+    options: &PrintOptions,
+) -> io::Result<()> {
+    for location in locations {
+        writeln!(
+            out,
+            "{} ({}, {}): {}, {}",
+            location.name(),
+            location.country(),
+            format_state(location.state(), location.country(), options),
+            format_coordinate(*location.lat(), options),
+            format_coordinate(*location.lon(), options)
+        )?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+fn locations_table(locations: &[CityLocation], options: &PrintOptions) -> Table {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
-    table.set_titles(row!["City", "Country", "State", "Weather", "Degrees"]);
+    table.set_titles(row!["City", "Country", "State", "Latitude", "Longitude"]);
 
-    for weather in weathers {
+    for location in locations {
         table.add_row(row![
-            weather.city_name(),
-            weather.country(),
-            weather.state().as_deref().unwrap_or(""),
-            weather.weather(),
-            format!("{}°", *weather.temperature() as i16)
+            location.name(),
+            location.country(),
+            format_state(location.state(), location.country(), options),
+            format_coordinate(*location.lat(), options),
+            format_coordinate(*location.lon(), options)
         ]);
     }
 
-    table.printstd();
-    println!();
+    table
 }
 
-fn print_weathers_json(weathers: Vec<CityWeather>) {
-    println!(
-        "{}",
-        serde_json::to_string_pretty(&weathers).unwrap_or_default()
-    );
-    println!();
-}
+fn format_state<'a>(state: &'a Option<String>, country: &str, options: &PrintOptions) -> &'a str {
+    let state = state.as_deref().unwrap_or("");
 
-pub fn init_tracing(level: Level) {
-    let subscriber = FmtSubscriber::builder().with_max_level(level).finish();
+    if options.expand_abbreviations && country == "US" {
+        utils::us_state_name(state).unwrap_or(state)
+    } else {
+        state
+    }
+}
 
-    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+fn format_coordinate(coordinate: f64, options: &PrintOptions) -> String {
+    format!("{:.*}", options.coordinate_precision, coordinate)
 }
 
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn test() {}
+/// Prints an OpenStreetMap link for every location `city` resolves to and opens the
+/// first one in the default browser. Without a graphical session the links are only
+/// printed.
+#[cfg(feature = "map")]
+pub async fn open_city_map(app: &Client<Connected>, city: &str) -> Result<(), Box<dyn Error>> {
+    let locations = app.get_locations(city.trim()).await?;
+    let first = locations
+        .first()
+        .ok_or_else(|| format!("No results found for \"{}\"", city.trim()))?;
+
+    for location in &locations {
+        println!(
+            "{} ({}, {}): {}",
+            location.name(),
+            location.country(),
+            location.state().as_deref().unwrap_or(""),
+            map_url(location)
+        );
+    }
+    println!();
+
+    let headless = cfg!(target_os = "linux")
+        && std::env::var_os("DISPLAY").is_none()
+        && std::env::var_os("WAYLAND_DISPLAY").is_none();
+
+    if !headless {
+        if let Err(error) = webbrowser::open(&map_url(first)) {
+            warn!("failed to open the map in a browser: {}", error);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "map")]
+fn map_url(location: &CityLocation) -> String {
+    format!(
+        "https://www.openstreetmap.org/?mlat={lat}&mlon={lon}#map=12/{lat}/{lon}",
+        lat = location.lat(),
+        lon = location.lon()
+    )
+}
+
+pub async fn print_city_weather_diff(
+    app: &Client<Connected>,
+    first_city: &str,
+    second_city: &str,
+    options: &PrintOptions,
+) -> Result<(), Box<dyn Error>> {
+    let first = get_best_match(app, first_city).await?;
+    let second = get_best_match(app, second_city).await?;
+
+    print_with(options, |out| write_diff(out, &first, &second, options))
+}
+
+fn write_diff(
+    out: &mut dyn Write,
+    first: &CityWeather,
+    second: &CityWeather,
+    options: &PrintOptions,
+) -> Result<(), Box<dyn Error>> {
+    match options.output_type {
+        OutputType::Table => {
+            let table = diff_table(first, second, options);
+
+            if table_fits_terminal(&table, options) {
+                write_table(out, &table)?;
+            } else {
+                write_diff_simple(out, first, second, options)?;
+            }
+            write_attribution(out, options)?;
+        }
+        OutputType::Simple => {
+            write_diff_simple(out, first, second, options)?;
+            write_attribution(out, options)?;
+        }
+        OutputType::Json => write_json(out, &diff_value(first, second), options)?,
+        OutputType::Tsv | OutputType::Csv => write_delimited(
+            out,
+            &WEATHER_COLUMNS,
+            [first, second].into_iter().map(weather_record),
+            options.output_type.delimiter(),
+            options,
+        )?,
+        #[cfg(feature = "templates")]
+        OutputType::Template => write_template(out, &diff_value(first, second), options)?,
+    };
+
+    Ok(())
+}
+
+async fn get_best_match(
+    app: &Client<Connected>,
+    city: &str,
+) -> Result<CityWeather, Box<dyn Error>> {
+    app.get_weather_best_match(city.trim())
+        .await?
+        .ok_or_else(|| format!("No results found for \"{}\"", city.trim()).into())
+}
+
+/// The differences from `first` to `second`, for the readings both of them have.
+/// Cities shown in different units, as with `--units auto`, are compared in metric.
+#[derive(Serialize)]
+struct WeatherDeltas {
+    units: Units,
+    wind_units: Units,
+    temperature: f64,
+    feels_like: Option<f64>,
+    humidity: Option<i16>,
+    wind_speed: Option<f64>,
+    cloudiness: Option<i16>,
+}
+
+impl WeatherDeltas {
+    fn new(first: &CityWeather, second: &CityWeather) -> Self {
+        let shared = |first: Units, second: Units| match first == second {
+            true => first,
+            false => Units::Metric,
+        };
+        let units = shared(*first.units(), *second.units());
+        let wind_units = shared(*first.wind_units(), *second.wind_units());
+
+        let temperature = |weather: &CityWeather, value: f64| {
+            utils::convert_temperature(value, *weather.units(), units)
+        };
+        let speed = |weather: &CityWeather, value: f64| {
+            utils::convert_speed(value, *weather.wind_units(), wind_units)
+        };
+        let percentage = |first: Option<u8>, second: Option<u8>| {
+            first
+                .zip(second)
+                .map(|(first, second)| i16::from(second) - i16::from(first))
+        };
+
+        WeatherDeltas {
+            units,
+            wind_units,
+            temperature: temperature(second, *second.temperature())
+                - temperature(first, *first.temperature()),
+            feels_like: first
+                .feels_like()
+                .zip(*second.feels_like())
+                .map(|(a, b)| temperature(second, b) - temperature(first, a)),
+            humidity: percentage(*first.humidity(), *second.humidity()),
+            wind_speed: first
+                .wind_speed()
+                .zip(*second.wind_speed())
+                .map(|(a, b)| speed(second, b) - speed(first, a)),
+            cloudiness: percentage(*first.cloudiness(), *second.cloudiness()),
+        }
+    }
+
+    fn format_temperature(&self, options: &PrintOptions) -> String {
+        format_temperature_delta(Some(self.temperature), self.units, options)
+    }
+
+    fn format_feels_like(&self, options: &PrintOptions) -> String {
+        format_temperature_delta(self.feels_like, self.units, options)
+    }
+
+    fn format_wind_speed(&self) -> String {
+        self.wind_speed
+            .map(|speed| format!("{:+.1} {}", speed, self.wind_units.speed_symbol()))
+            .unwrap_or_default()
+    }
+}
+
+/// The signed `delta` in `units`, spaced like the temperatures it is the difference of.
+fn format_temperature_delta(delta: Option<f64>, units: Units, options: &PrintOptions) -> String {
+    delta
+        .map(|delta| match format_degrees(delta, units, options) {
+            degrees if delta as i16 >= 0 => format!("+{}", degrees),
+            degrees => degrees,
+        })
+        .unwrap_or_default()
+}
+
+fn format_percentage_delta(delta: Option<i16>) -> String {
+    delta
+        .map(|delta| format!("{:+}%", delta))
+        .unwrap_or_default()
+}
+
+fn format_location(weather: &CityWeather) -> String {
+    format!("{} ({})", weather.city_name(), weather.country())
+}
+
+fn write_diff_simple(
+    out: &mut dyn Write,
+    first: &CityWeather,
+    second: &CityWeather,
+    options: &PrintOptions,
+) -> io::Result<()> {
+    let deltas = WeatherDeltas::new(first, second);
+    let changes = [
+        Some(deltas.format_temperature(options)),
+        deltas
+            .feels_like
+            .map(|_| format!("feels like {}", deltas.format_feels_like(options))),
+        deltas
+            .humidity
+            .map(|_| format!("{} humidity", format_percentage_delta(deltas.humidity))),
+        deltas
+            .wind_speed
+            .map(|_| format!("{} wind", deltas.format_wind_speed())),
+        deltas
+            .cloudiness
+            .map(|_| format!("{} clouds", format_percentage_delta(deltas.cloudiness))),
+    ];
+
+    writeln!(
+        out,
+        "{} | {} | {}",
+        format_diff_side(first, options),
+        format_diff_side(second, options),
+        changes.into_iter().flatten().join(", ")
+    )?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// One city of the simple diff output, leaving out the readings the service did not
+/// report.
+fn format_diff_side(weather: &CityWeather, options: &PrintOptions) -> String {
+    let readings = [
+        Some(weather.weather().to_owned()),
+        Some(format_temperature(weather, options)),
+        weather
+            .feels_like()
+            .map(|_| format!("feels like {}", format_feels_like(weather, options))),
+        weather
+            .humidity()
+            .map(|humidity| format!("{}% humidity", humidity)),
+        weather
+            .wind_speed()
+            .map(|_| format!("{} wind", format_wind_speed(weather))),
+    ];
+
+    format!(
+        "{}: {}",
+        format_location(weather),
+        readings.into_iter().flatten().join(", ")
+    )
+}
+
+fn diff_table(first: &CityWeather, second: &CityWeather, options: &PrintOptions) -> Table {
+    let deltas = WeatherDeltas::new(first, second);
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row![
+        "",
+        format_location(first),
+        format_location(second),
+        "Delta"
+    ]);
+    table.add_row(row![
+        "State",
+        format_state(first.state(), first.country(), options),
+        format_state(second.state(), second.country(), options),
+        ""
+    ]);
+    table.add_row(row!["Weather", first.weather(), second.weather(), ""]);
+    table.add_row(row![
+        "Degrees",
+        format_temperature(first, options),
+        format_temperature(second, options),
+        deltas.format_temperature(options)
+    ]);
+    table.add_row(row![
+        "Feels like",
+        format_feels_like(first, options),
+        format_feels_like(second, options),
+        deltas.format_feels_like(options)
+    ]);
+    table.add_row(row![
+        "Humidity",
+        format_percentage(*first.humidity()),
+        format_percentage(*second.humidity()),
+        format_percentage_delta(deltas.humidity)
+    ]);
+    table.add_row(row![
+        "Wind",
+        format_wind_speed(first),
+        format_wind_speed(second),
+        deltas.format_wind_speed()
+    ]);
+    table.add_row(row![
+        "Clouds",
+        format_cloudiness(first),
+        format_cloudiness(second),
+        format_percentage_delta(deltas.cloudiness)
+    ]);
+
+    table
+}
+
+fn diff_value(first: &CityWeather, second: &CityWeather) -> Value {
+    serde_json::json!({
+        "first": first,
+        "second": second,
+        "deltas": WeatherDeltas::new(first, second),
+    })
+}
+
+fn write_weathers_simple(
+    out: &mut dyn Write,
+    weathers: &[CityWeather],
+    options: &PrintOptions,
+) -> io::Result<()> {
+    for line in render_rows(weathers, |weather| {
+        format!(
+            "{} ({}, {}): {}, {}{}{}",
+            paint(weather.city_name(), BOLD, options),
+            weather.country(),
+            format_state(weather.state(), weather.country(), options),
+            weather.weather(),
+            paint_temperature(weather, options),
+            weather
+                .feels_like()
+                .map(|feels_like| format!(
+                    " (feels like {})",
+                    format_degrees(feels_like, *weather.units(), options)
+                ))
+                .unwrap_or_default(),
+            weather
+                .cloudiness()
+                .map(|cloudiness| format!(", {}% clouds", cloudiness))
+                .unwrap_or_default()
+        )
+    }) {
+        writeln!(out, "{}", line)?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+fn weathers_table(weathers: &[CityWeather], options: &PrintOptions) -> Table {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row![
+        "City",
+        "Country",
+        "State",
+        "Weather",
+        "Degrees",
+        "Feels like",
+        "Humidity",
+        "Wind",
+        "Clouds"
+    ]);
+
+    for row in render_rows(weathers, |weather| {
+        row![
+            weather.city_name(),
+            weather.country(),
+            format_state(weather.state(), weather.country(), options),
+            weather.weather(),
+            format_temperature(weather, options),
+            format_feels_like(weather, options),
+            format_percentage(*weather.humidity()),
+            format_wind_speed(weather),
+            format_cloudiness(weather)
+        ]
+    }) {
+        table.add_row(row);
+    }
+
+    table
+}
+
+fn write_forecasts_simple(
+    out: &mut dyn Write,
+    forecasts: &[CityForecast],
+    options: &PrintOptions,
+) -> io::Result<()> {
+    for forecast in forecasts {
+        writeln!(
+            out,
+            "{} ({}, {}):",
+            forecast.city_name(),
+            forecast.country(),
+            format_state(forecast.state(), forecast.country(), options)
+        )?;
+
+        for day in forecast.days() {
+            writeln!(
+                out,
+                "  {}: {}, {} to {}",
+                day.date(),
+                day.weather(),
+                format_degrees(*day.min_temperature(), *forecast.units(), options),
+                format_degrees(*day.max_temperature(), *forecast.units(), options)
+            )?;
+        }
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+fn forecasts_table(forecasts: &[CityForecast], options: &PrintOptions) -> Table {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row![
+        "City", "Country", "State", "Date", "Weather", "Min", "Max"
+    ]);
+
+    for forecast in forecasts {
+        for day in forecast.days() {
+            table.add_row(row![
+                forecast.city_name(),
+                forecast.country(),
+                format_state(forecast.state(), forecast.country(), options),
+                day.date(),
+                day.weather(),
+                format_degrees(*day.min_temperature(), *forecast.units(), options),
+                format_degrees(*day.max_temperature(), *forecast.units(), options)
+            ]);
+        }
+    }
+
+    table
+}
+
+/// Result sets with at least this many rows are rendered in parallel.
+const PARALLEL_RENDER_THRESHOLD: usize = 1000;
+
+/// Renders every item with `render`, spreading the work over the rayon thread pool
+/// for large result sets. The rendered rows keep the order of `items`.
+fn render_rows<T, R, F>(items: &[T], render: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    if items.len() >= PARALLEL_RENDER_THRESHOLD {
+        items.par_iter().map(render).collect()
+    } else {
+        items.iter().map(render).collect()
+    }
+}
+
+fn write_table(out: &mut dyn Write, table: &Table) -> io::Result<()> {
+    table.print(out)?;
+    writeln!(out)
+}
+
+/// Whether the rendered `table` fits the width of the terminal stdout is attached to.
+/// Output that is not a terminal, or `--force-table`, always keeps the table. The
+/// first time a table does not fit, a note about the fallback is printed to stderr.
+fn table_fits_terminal(table: &Table, options: &PrintOptions) -> bool {
+    static NARROW_TERMINAL_NOTE: Once = Once::new();
+
+    if options.force_table || options.output_file.is_some() {
+        return true;
+    }
+
+    let Some((Width(width), _)) = terminal_size() else {
+        return true;
+    };
+
+    let fits = table
+        .to_string()
+        .lines()
+        .all(|line| visible_width(line) <= usize::from(width));
+
+    if !fits {
+        NARROW_TERMINAL_NOTE.call_once(|| {
+            eprintln!("The table is too wide for the terminal, showing simple output instead.");
+        });
+    }
+
+    fits
+}
+
+fn format_cloudiness(weather: &CityWeather) -> String {
+    format_percentage(*weather.cloudiness())
+}
+
+fn format_percentage(percentage: Option<u8>) -> String {
+    percentage
+        .map(|percentage| format!("{}%", percentage))
+        .unwrap_or_default()
+}
+
+fn format_feels_like(weather: &CityWeather, options: &PrintOptions) -> String {
+    weather
+        .feels_like()
+        .map(|feels_like| format_degrees(feels_like, *weather.units(), options))
+        .unwrap_or_default()
+}
+
+fn format_wind_speed(weather: &CityWeather) -> String {
+    weather
+        .wind_speed()
+        .map(|speed| format!("{:.1} {}", speed, weather.wind_units().speed_symbol()))
+        .unwrap_or_default()
+}
+
+fn format_temperature(weather: &CityWeather, options: &PrintOptions) -> String {
+    format_degrees(*weather.temperature(), *weather.units(), options)
+}
+
+fn format_degrees(temperature: f64, units: Units, options: &PrintOptions) -> String {
+    options
+        .temperature_spacing
+        .format(temperature as i16, units)
+}
+
+const BOLD: &str = "1";
+const BLUE: &str = "34";
+const GREEN: &str = "32";
+const RED: &str = "31";
+
+/// Wraps `text` in the ANSI style `code` when colors are enabled.
+fn paint(text: &str, code: &str, options: &PrintOptions) -> String {
+    if options.color {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_owned()
+    }
+}
+
+/// Blue below 10°C, green up to 25°C and red above.
+fn temperature_color(weather: &CityWeather) -> &'static str {
+    match celsius(weather) {
+        celsius if celsius < 10.0 => BLUE,
+        celsius if celsius <= 25.0 => GREEN,
+        _ => RED,
+    }
+}
+
+fn paint_temperature(weather: &CityWeather, options: &PrintOptions) -> String {
+    paint(
+        &format_temperature(weather, options),
+        temperature_color(weather),
+        options,
+    )
+}
+
+/// Renders the weathers table with bold city names and colored temperatures. The
+/// styles are added after prettytable laid out the columns, since it counts escapes
+/// toward the column widths.
+fn paint_weathers_table(table: &Table, weathers: &[CityWeather], options: &PrintOptions) -> String {
+    let paint_cell = |cell: &str, code: &str| match cell.trim() {
+        "" => cell.to_owned(),
+        text => cell.replacen(text, &paint(text, code, options), 1),
+    };
+
+    // Without row separators, the first `|` line holds the titles and every further
+    // one a row, in the order of `weathers`.
+    let mut rows = weathers.iter();
+    let mut rendered = String::new();
+
+    for (index, line) in table.to_string().lines().enumerate() {
+        let row = line.starts_with('|') && index > 1;
+
+        match row.then(|| rows.next()).flatten() {
+            Some(weather) => {
+                let cells: Vec<String> = line
+                    .split('|')
+                    .enumerate()
+                    .map(|(column, cell)| match column {
+                        1 => paint_cell(cell, BOLD),
+                        5 => paint_cell(cell, temperature_color(weather)),
+                        _ => cell.to_owned(),
+                    })
+                    .collect();
+                rendered.push_str(&cells.join("|"));
+            }
+            None => rendered.push_str(line),
+        }
+        rendered.push('\n');
+    }
+
+    rendered
+}
+
+fn celsius(weather: &CityWeather) -> f64 {
+    utils::convert_temperature(*weather.temperature(), *weather.units(), Units::Metric)
+}
+
+/// The number of characters `line` takes up on a terminal, skipping ANSI styles.
+fn visible_width(line: &str) -> usize {
+    let mut escaped = false;
+
+    line.chars()
+        .filter(|&c| {
+            let visible = !escaped && c != '\x1b';
+            escaped = (escaped || c == '\x1b') && c != 'm';
+            visible
+        })
+        .count()
+}
+
+const WEATHER_COLUMNS: [&str; 11] = [
+    "city",
+    "country",
+    "state",
+    "weather",
+    "temperature",
+    "units",
+    "cloudiness",
+    "feels_like",
+    "humidity",
+    "wind_speed",
+    "wind_units",
+];
+
+const LOCATION_COLUMNS: [&str; 5] = ["city", "country", "state", "lat", "lon"];
+
+fn weather_record(weather: &CityWeather) -> Vec<String> {
+    vec![
+        weather.city_name().to_owned(),
+        weather.country().to_owned(),
+        weather.state().clone().unwrap_or_default(),
+        weather.weather().to_owned(),
+        weather.temperature().to_string(),
+        weather
+            .units()
+            .to_possible_value()
+            .unwrap_or_default()
+            .get_name()
+            .to_owned(),
+        weather
+            .cloudiness()
+            .map(|cloudiness| cloudiness.to_string())
+            .unwrap_or_default(),
+        weather
+            .feels_like()
+            .map(|feels_like| feels_like.to_string())
+            .unwrap_or_default(),
+        weather
+            .humidity()
+            .map(|humidity| humidity.to_string())
+            .unwrap_or_default(),
+        weather
+            .wind_speed()
+            .map(|speed| speed.to_string())
+            .unwrap_or_default(),
+        weather
+            .wind_units()
+            .to_possible_value()
+            .unwrap_or_default()
+            .get_name()
+            .to_owned(),
+    ]
+}
+
+const FORECAST_COLUMNS: [&str; 8] = [
+    "city",
+    "country",
+    "state",
+    "date",
+    "weather",
+    "min_temperature",
+    "max_temperature",
+    "units",
+];
+
+/// One record per forecast day.
+fn forecast_records(forecast: &CityForecast) -> impl Iterator<Item = Vec<String>> + '_ {
+    forecast.days().iter().map(|day| {
+        vec![
+            forecast.city_name().to_owned(),
+            forecast.country().to_owned(),
+            forecast.state().clone().unwrap_or_default(),
+            day.date().to_owned(),
+            day.weather().to_owned(),
+            day.min_temperature().to_string(),
+            day.max_temperature().to_string(),
+            forecast.units().to_string(),
+        ]
+    })
+}
+
+/// Like JSON, delimited output is read by programs and keeps full coordinate precision.
+fn location_record(location: &CityLocation) -> Vec<String> {
+    vec![
+        location.name().to_owned(),
+        location.country().to_owned(),
+        location.state().clone().unwrap_or_default(),
+        location.lat().to_string(),
+        location.lon().to_string(),
+    ]
+}
+
+/// Writes the records separated by `delimiter`, keeping every record on one row. CSV
+/// quotes fields containing the delimiter, quotes or line breaks; TSV is never quoted,
+/// so that `cut -f` works, and escapes tabs, line breaks and backslashes instead.
+fn write_delimited(
+    out: &mut dyn Write,
+    header: &[&str],
+    records: impl Iterator<Item = Vec<String>>,
+    delimiter: u8,
+    options: &PrintOptions,
+) -> io::Result<()> {
+    let tsv = matches!(options.output_type, OutputType::Tsv);
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .quote_style(match tsv {
+            true => csv::QuoteStyle::Never,
+            false => csv::QuoteStyle::Necessary,
+        })
+        .from_writer(out);
+
+    if !options.no_header {
+        writer.write_record(header)?;
+    }
+
+    for record in records {
+        match tsv {
+            true => writer.write_record(record.iter().map(|field| escape_tsv(field)))?,
+            false => writer.write_record(record)?,
+        }
+    }
+
+    writer.flush()
+}
+
+fn escape_tsv(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn write_attribution(out: &mut dyn Write, options: &PrintOptions) -> io::Result<()> {
+    if options.attribution {
+        writeln!(out, "{}", ATTRIBUTION)?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+fn write_json<T: Serialize + ?Sized>(
+    out: &mut dyn Write,
+    data: &T,
+    options: &PrintOptions,
+) -> io::Result<()> {
+    // The plain shape needs no rewriting, so it is serialized as it is.
+    if !options.json_legacy && !options.json_flat && !options.attribution {
+        serde_json::to_writer_pretty(&mut *out, data)?;
+        writeln!(out)?;
+        return writeln!(out);
+    }
+
+    let mut data = serde_json::to_value(data)?;
+
+    if options.json_legacy {
+        legacy_temperatures(&mut data);
+    }
+
+    if options.json_flat {
+        data = flatten_json(data);
+    }
+
+    if options.attribution {
+        data = serde_json::json!({ "attribution": ATTRIBUTION, "results": data });
+    }
+
+    serde_json::to_writer_pretty(&mut *out, &data)?;
+    writeln!(out)?;
+    writeln!(out)
+}
+
+/// Replaces every `"temperature"` and `"feels_like"` object of the form
+/// `{ "value": .., "unit": .. }` by its bare value.
+fn legacy_temperatures(value: &mut Value) {
+    match value {
+        Value::Array(values) => values.iter_mut().for_each(legacy_temperatures),
+        Value::Object(object) => {
+            for key in ["temperature", "feels_like"] {
+                if let Some(temperature) = object.get_mut(key) {
+                    if let Some(value) = temperature.get("value").cloned() {
+                        *temperature = value;
+                    }
+                }
+            }
+
+            object.values_mut().for_each(legacy_temperatures);
+        }
+        _ => {}
+    }
+}
+
+/// The optional objects of the JSON output with their fields, so that flattening an
+/// absent one yields the same keys as a present one, e.g. `"wind_speed": null`.
+const OPTIONAL_OBJECTS: [(&str, &[&str]); 2] = [
+    ("feels_like", &["value", "unit"]),
+    ("wind", &["speed", "unit"]),
+];
+
+/// Flattens nested objects into their parent using underscore-joined keys, so that
+/// `{ "wind": { "speed": 3 } }` becomes `{ "wind_speed": 3 }`. A top-level array is
+/// flattened element-wise; other arrays are kept as they are.
+fn flatten_json(value: Value) -> Value {
+    fn flatten_into(
+        prefix: Option<&str>,
+        object: Map<String, Value>,
+        flat: &mut Map<String, Value>,
+    ) {
+        for (name, value) in object {
+            let key = match prefix {
+                Some(prefix) => format!("{}_{}", prefix, name),
+                None => name.clone(),
+            };
+            let fields = OPTIONAL_OBJECTS
+                .iter()
+                .find(|(optional, _)| *optional == name)
+                .map(|(_, fields)| *fields);
+
+            match (value, fields) {
+                (Value::Null, Some(fields)) => {
+                    for field in fields {
+                        flat.insert(format!("{}_{}", key, field), Value::Null);
+                    }
+                }
+                (Value::Object(object), _) => flatten_into(Some(&key), object, flat),
+                (value, _) => {
+                    flat.insert(key, value);
+                }
+            }
+        }
+    }
+
+    match value {
+        Value::Array(values) => Value::Array(values.into_iter().map(flatten_json).collect()),
+        Value::Object(object) => {
+            let mut flat = Map::new();
+            flatten_into(None, object, &mut flat);
+            Value::Object(flat)
+        }
+        value => value,
+    }
+}
+
+/// Renders `data` with the configured template; the template sees the same structure
+/// as the JSON output, e.g. `{{#each this}}{{city_name}}{{/each}}` for weathers.
+#[cfg(feature = "templates")]
+fn write_template<T: Serialize>(
+    out: &mut dyn Write,
+    data: &T,
+    options: &PrintOptions,
+) -> Result<(), Box<dyn Error>> {
+    let template = options
+        .template
+        .as_deref()
+        .ok_or("The template output requires a template file (--template-file).")?;
+
+    handlebars::Handlebars::new().render_template_to_write(template, data, &mut *out)?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// Whether `error` comes from writing to a closed pipe, e.g. when piping into `head`,
+/// which is not worth reporting.
+pub fn is_broken_pipe(error: &(dyn Error + 'static)) -> bool {
+    error
+        .downcast_ref::<io::Error>()
+        .is_some_and(|error| error.kind() == io::ErrorKind::BrokenPipe)
+}
+
+pub fn init_tracing(level: Level) {
+    // Logs go to stderr so that stdout only carries results when piped.
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(level)
+        .with_writer(io::stderr)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, error::Error, fs, io, process};
+
+    use itertools::Itertools;
+    use serde_json::{json, Value};
+
+    use super::{
+        check_coordinates, diff_value, flatten_json, format_temperature_delta, is_broken_pipe,
+        legacy_temperatures, paint, parse_coordinates, render_rows, render_weathers, visible_width,
+        write_delimited, AppConfig, Client, Config, Connected, OutputType, PrintOptions,
+        SessionHistory, TemperatureSpacing, WeatherError, WeatherSummary, BOLD,
+        PARALLEL_RENDER_THRESHOLD,
+    };
+    use crate::weather_client::Units;
+
+    #[test]
+    fn test() {}
+
+    #[test]
+    fn broken_pipe_detection() {
+        let broken: Box<dyn Error> = io::Error::from(io::ErrorKind::BrokenPipe).into();
+        let other: Box<dyn Error> = io::Error::from(io::ErrorKind::NotFound).into();
+        let message: Box<dyn Error> = "broken pipe".into();
+
+        assert!(is_broken_pipe(broken.as_ref()));
+        assert!(!is_broken_pipe(other.as_ref()));
+        assert!(!is_broken_pipe(message.as_ref()));
+    }
+
+    #[test]
+    fn render_rows_keeps_order() {
+        let items: Vec<usize> = (0..PARALLEL_RENDER_THRESHOLD * 3).collect();
+        let rendered = render_rows(&items, |item| item.to_string());
+
+        assert_eq!(rendered.len(), items.len());
+        assert!(rendered
+            .iter()
+            .zip(&items)
+            .all(|(row, item)| *row == item.to_string()));
+    }
+
+    #[test]
+    fn session_history_keeps_first_query_order() {
+        let mut history = SessionHistory::default();
+        history.record("London", Some("12°C".to_owned()));
+        history.record("Paris", None);
+        history.record("london", Some("13°C".to_owned()));
+
+        assert_eq!(history.get(1), Some("London"));
+        assert_eq!(history.get(2), Some("Paris"));
+        assert_eq!(history.get(0), None);
+        assert_eq!(history.get(3), None);
+        assert_eq!(history.entries[0].1.as_deref(), Some("13°C"));
+    }
+
+    #[test]
+    fn flatten_json_joins_nested_keys() {
+        let nested = json!([{
+            "city_name": "London",
+            "wind": { "speed": 3.5, "gust": { "max": 7 } },
+            "sys": { "sunrise": 1700000000 },
+            "tags": [{ "kept": true }],
+        }]);
+
+        assert_eq!(
+            flatten_json(nested),
+            json!([{
+                "city_name": "London",
+                "wind_speed": 3.5,
+                "wind_gust_max": 7,
+                "sys_sunrise": 1700000000,
+                "tags": [{ "kept": true }],
+            }])
+        );
+    }
+
+    #[cfg(feature = "pluscode")]
+    #[test]
+    fn plus_code_decoding() {
+        let (lat, lon) = super::decode_plus_code("9C3XGV4C+XV").unwrap();
+
+        assert!((lat - 51.507_4).abs() < 0.001);
+        assert!((lon - -0.127_7).abs() < 0.001);
+        assert!(super::decode_plus_code("GV4C+XV").is_err());
+        assert!(super::decode_plus_code("not a code").is_err());
+    }
+
+    #[test]
+    fn legacy_temperatures_are_bare_numbers() {
+        let mut value = json!({
+            "first": {
+                "temperature": { "value": 12.5, "unit": "C" },
+                "feels_like": { "value": 11.0, "unit": "C" },
+                "units": "metric",
+            },
+            "second": { "temperature": { "value": 50.0, "unit": "F" }, "units": "imperial" },
+            "deltas": { "temperature": 1.0 },
+        });
+        legacy_temperatures(&mut value);
+
+        assert_eq!(
+            value,
+            json!({
+                "first": { "temperature": 12.5, "feels_like": 11.0, "units": "metric" },
+                "second": { "temperature": 50.0, "units": "imperial" },
+                "deltas": { "temperature": 1.0 },
+            })
+        );
+    }
+
+    #[test]
+    fn flatten_json_keeps_flat_values() {
+        assert_eq!(flatten_json(json!({ "a": 1 })), json!({ "a": 1 }));
+        assert_eq!(flatten_json(json!(null)), json!(null));
+        assert_eq!(
+            flatten_json(json!({ "feels_like": null, "wind": null })),
+            json!({
+                "feels_like_value": null,
+                "feels_like_unit": null,
+                "wind_speed": null,
+                "wind_unit": null
+            })
+        );
+    }
+
+    #[test]
+    fn temperature_spacing_variants() {
+        assert_eq!(
+            TemperatureSpacing::Compact.format(12, Units::Metric),
+            "12°C"
+        );
+        assert_eq!(
+            TemperatureSpacing::BeforeDegree.format(12, Units::Metric),
+            "12 °C"
+        );
+        assert_eq!(
+            TemperatureSpacing::BeforeUnit.format(-3, Units::Imperial),
+            "-3° F"
+        );
+        assert_eq!(
+            TemperatureSpacing::Compact.format(285, Units::Standard),
+            "285K"
+        );
+        assert_eq!(
+            TemperatureSpacing::BeforeUnit.format(285, Units::Standard),
+            "285 K"
+        );
+    }
+
+    #[test]
+    fn temperature_deltas_follow_units_and_spacing() {
+        let mut options = PrintOptions::new(OutputType::Table);
+        assert_eq!(
+            format_temperature_delta(Some(3.4), Units::Metric, &options),
+            "+3°C"
+        );
+        assert_eq!(
+            format_temperature_delta(Some(-5.0), Units::Standard, &options),
+            "-5K"
+        );
+        assert_eq!(format_temperature_delta(None, Units::Metric, &options), "");
+
+        options.temperature_spacing = TemperatureSpacing::BeforeUnit;
+        assert_eq!(
+            format_temperature_delta(Some(0.0), Units::Imperial, &options),
+            "+0° F"
+        );
+    }
+
+    #[test]
+    fn api_key_from_environment() {
+        let path = env::temp_dir().join(format!("city_weather_config_{}.toml", process::id()));
+        fs::write(&path, "output = \"Table\"\n\n[client]\nlang = \"en\"\n").unwrap();
+
+        assert!(matches!(
+            AppConfig::load_with_env_key(&path, None),
+            Err(WeatherError::Config { .. })
+        ));
+
+        let from_env = || Some("from-env".to_owned());
+        let config = AppConfig::load_with_env_key(&path, from_env()).unwrap();
+        assert_eq!(config.client.api_key(), "from-env");
+
+        fs::write(
+            &path,
+            "output = \"Table\"\n\n[client]\napi_key = \"from-file\"\nlang = \"en\"\n",
+        )
+        .unwrap();
+        let config = AppConfig::load_with_env_key(&path, from_env()).unwrap();
+        assert_eq!(config.client.api_key(), "from-env");
+
+        let config = AppConfig::load_with_env_key(&path, Some(String::new())).unwrap();
+        assert_eq!(config.client.api_key(), "from-file");
+        assert_eq!(config.temperature_spacing, TemperatureSpacing::Compact);
+
+        fs::write(
+            &path,
+            "output = \"Table\"\ntemperature_spacing = \"before-unit\"\n\n[client]\napi_key = \"from-file\"\nlang = \"en\"\n",
+        )
+        .unwrap();
+        let config = AppConfig::load_with_env_key(&path, None).unwrap();
+        assert_eq!(config.temperature_spacing, TemperatureSpacing::BeforeUnit);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn render_weathers_returns_the_output() {
+        let options = PrintOptions::new(OutputType::Json);
+        assert_eq!(render_weathers(&[], &options).unwrap(), "[]\n\n");
+
+        let options = PrintOptions {
+            attribution: true,
+            ..PrintOptions::new(OutputType::Simple)
+        };
+        assert_eq!(
+            render_weathers(&[], &options).unwrap(),
+            "\nWeather data by OpenWeatherMap\n\n"
+        );
+    }
+
+    #[test]
+    fn colors_are_only_added_when_enabled() {
+        let mut options = PrintOptions::new(OutputType::Table);
+        assert_eq!(paint("London", BOLD, &options), "London");
+
+        options.color = true;
+        let painted = paint("London", BOLD, &options);
+        assert_eq!(painted, "\x1b[1mLondon\x1b[0m");
+        assert_eq!(visible_width(&painted), 6);
+    }
+
+    /// A client answering from a fixture with London at 12.3 degrees in `units`, written
+    /// to a file named after `test` so that tests do not share it.
+    fn fixture_client(test: &str, units: Units) -> Client<Connected> {
+        let path = env::temp_dir().join(format!("city_weather_{}_{}.json", test, process::id()));
+        fs::write(
+            &path,
+            r#"{ "London": {
+                "geocode": [{ "name": "London", "lat": 51.5, "lon": -0.12, "country": "GB", "state": "England" }],
+                "weather": { "weather": [{ "main": "Clouds", "description": "broken clouds" }], "main": { "temp": 12.3 }, "clouds": { "all": 75 } }
+            } }"#,
+        )
+        .unwrap();
+
+        let mut config: Config = toml::from_str("lang = \"en\"").unwrap();
+        config.set_units(units);
+        let client = Client::new_mock(config, &path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        client
+    }
+
+    #[tokio::test]
+    async fn summaries_convert_mixed_units() {
+        let mut weathers = Vec::new();
+        for units in [Units::Metric, Units::Imperial] {
+            let client = fixture_client(&format!("summary_{}", units), units);
+            weathers.extend(client.get_weather("London").await.unwrap());
+        }
+
+        let summary = WeatherSummary::new(&weathers);
+        assert_eq!(summary.units, Some(Units::Metric));
+        assert_eq!(summary.min_temperature.unwrap().round(), -11.0);
+        assert_eq!(summary.max_temperature, Some(12.3));
+
+        let summary = WeatherSummary::new(&weathers[1..]);
+        assert_eq!(summary.units, Some(Units::Imperial));
+        assert_eq!(summary.average_temperature, Some(12.3));
+        assert_eq!(WeatherSummary::new(&[]).units, None);
+    }
+
+    #[tokio::test]
+    async fn diff_deltas_compare_mixed_units() {
+        let london = |units| async move {
+            fixture_client(&format!("diff_{}", units), units)
+                .get_weather_best_match("London")
+                .await
+                .unwrap()
+                .unwrap()
+        };
+        let (metric, imperial) = (london(Units::Metric).await, london(Units::Imperial).await);
+
+        let deltas = &diff_value(&metric, &imperial)["deltas"];
+        assert_eq!(deltas["units"], "metric");
+        assert_eq!(deltas["temperature"].as_f64().unwrap().round(), -23.0);
+        assert_eq!(deltas["cloudiness"], 0);
+        assert_eq!(deltas["humidity"], Value::Null);
+
+        let deltas = &diff_value(&imperial, &imperial)["deltas"];
+        assert_eq!(deltas["units"], "imperial");
+        assert_eq!(deltas["temperature"], 0.0);
+    }
+
+    #[tokio::test]
+    async fn weather_from_fixtures_is_rendered() {
+        let client = fixture_client("fixtures", Units::Metric);
+        let weathers = client.get_weather("london").await.unwrap();
+        let options = PrintOptions::new(OutputType::Simple);
+        assert_eq!(
+            render_weathers(&weathers, &options).unwrap(),
+            "London (GB, England): broken clouds, 12°C, 75% clouds\n\n"
+        );
+        assert!(client.get_weather("Paris").await.unwrap().is_empty());
+
+        // Both JSON paths keep the struct order and the numbers as the service sent them.
+        for attribution in [false, true] {
+            let options = PrintOptions {
+                attribution,
+                ..PrintOptions::new(OutputType::Json)
+            };
+            let json = render_weathers(&weathers, &options).unwrap();
+            assert!(json.contains("\"value\": 12.3,"), "{}", json);
+            assert!(json.find("\"weather\"") < json.find("\"city_name\""));
+        }
+    }
+
+    #[tokio::test]
+    async fn colored_tables_stay_aligned() {
+        let weathers = fixture_client("colors", Units::Metric)
+            .get_weather("London")
+            .await
+            .unwrap();
+        let options = PrintOptions {
+            color: true,
+            force_table: true,
+            ..PrintOptions::new(OutputType::Table)
+        };
+
+        let table = render_weathers(&weathers, &options).unwrap();
+        assert!(table.contains("\x1b[1mLondon\x1b[0m"));
+
+        let widths: Vec<usize> = table
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(visible_width)
+            .collect();
+        assert!(widths.iter().all_equal(), "{:?}", widths);
+    }
+
+    #[test]
+    fn csv_fields_are_quoted() {
+        let mut out = Vec::new();
+        let records = vec![vec![
+            "rain, heavy".to_owned(),
+            "say \"hi\"".to_owned(),
+            String::new(),
+        ]];
+        write_delimited(
+            &mut out,
+            &["weather", "city", "state"],
+            records.into_iter(),
+            OutputType::Csv.delimiter(),
+            &PrintOptions::new(OutputType::Csv),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "weather,city,state\n\"rain, heavy\",\"say \"\"hi\"\"\",\n"
+        );
+    }
+
+    #[test]
+    fn tsv_fields_are_escaped() {
+        let mut out = Vec::new();
+        let records = vec![vec![
+            "rain\theavy".to_owned(),
+            "say \"hi\"\nbye".to_owned(),
+            "C:\\weather".to_owned(),
+        ]];
+        write_delimited(
+            &mut out,
+            &["weather", "city", "state"],
+            records.into_iter(),
+            OutputType::Tsv.delimiter(),
+            &PrintOptions::new(OutputType::Tsv),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "weather\tcity\tstate\nrain\\theavy\tsay \"hi\"\\nbye\tC:\\\\weather\n"
+        );
+    }
+
+    #[test]
+    fn coordinate_validation() {
+        assert_eq!(parse_coordinates("51.5, -0.12").unwrap(), (51.5, -0.12));
+        assert_eq!(check_coordinates(-90.0, 180.0).unwrap(), (-90.0, 180.0));
+        assert!(check_coordinates(90.5, 0.0).is_err());
+        assert!(check_coordinates(0.0, -180.5).is_err());
+        assert!(parse_coordinates("51.5").is_err());
+        assert!(parse_coordinates("north,west").is_err());
+    }
 }
@@ -0,0 +1,153 @@
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use tracing::warn;
+
+static HITS: AtomicUsize = AtomicUsize::new(0);
+static MISSES: AtomicUsize = AtomicUsize::new(0);
+
+/// Response bodies by request, kept for `ttl` in memory and, given a directory, on
+/// disk so that they are reused by the next runs as well. A zero `ttl` disables it,
+/// except for the seeded bodies, which are served for the whole run.
+pub(crate) struct Cache {
+    ttl: Duration,
+    dir: Option<PathBuf>,
+    entries: Mutex<HashMap<String, (SystemTime, Vec<u8>)>>,
+    seeded: HashMap<String, Vec<u8>>,
+}
+
+impl Cache {
+    pub(crate) fn new(ttl: Duration, dir: Option<PathBuf>) -> Self {
+        Cache {
+            ttl,
+            dir,
+            entries: Mutex::new(HashMap::new()),
+            seeded: HashMap::new(),
+        }
+    }
+
+    /// Serves `body` for `key` for the whole run, whatever the TTL.
+    pub(crate) fn seed(&mut self, key: String, body: Vec<u8>) {
+        self.seeded.insert(key, body);
+    }
+
+    /// The body seeded for `key`, or the one stored for it unless it is older than the TTL.
+    pub(crate) fn get(&self, key: &str) -> Option<Vec<u8>> {
+        if let Some(body) = self.seeded.get(key) {
+            HITS.fetch_add(1, Ordering::Relaxed);
+            return Some(body.clone());
+        }
+
+        if self.ttl.is_zero() {
+            return None;
+        }
+
+        let entries = self.entries.lock().expect("cache lock poisoned");
+        let body = entries
+            .get(key)
+            .filter(|(stored, _)| self.is_fresh(*stored))
+            .map(|(_, body)| body.clone());
+        drop(entries);
+
+        let body = body.or_else(|| self.read_file(key));
+        match body {
+            Some(_) => HITS.fetch_add(1, Ordering::Relaxed),
+            None => MISSES.fetch_add(1, Ordering::Relaxed),
+        };
+
+        body
+    }
+
+    pub(crate) fn insert(&self, key: &str, body: &[u8]) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        self.entries
+            .lock()
+            .expect("cache lock poisoned")
+            .insert(key.to_owned(), (SystemTime::now(), body.to_vec()));
+
+        if let (Some(dir), Some(path)) = (&self.dir, self.file_path(key)) {
+            if let Err(error) = fs::create_dir_all(dir).and_then(|_| fs::write(&path, body)) {
+                warn!("failed to write cache file {}: {}", path.display(), error);
+            }
+        }
+    }
+
+    fn is_fresh(&self, stored: SystemTime) -> bool {
+        stored.elapsed().is_ok_and(|age| age < self.ttl)
+    }
+
+    fn read_file(&self, key: &str) -> Option<Vec<u8>> {
+        let path = self.file_path(key)?;
+        let modified = fs::metadata(&path).and_then(|metadata| metadata.modified());
+
+        if !self.is_fresh(modified.ok()?) {
+            return None;
+        }
+
+        fs::read(path).ok()
+    }
+
+    /// The file of `key`, named by its hash since keys are whole URLs.
+    fn file_path(&self, key: &str) -> Option<PathBuf> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+
+        Some(
+            self.dir
+                .as_ref()?
+                .join(format!("{:016x}.json", hasher.finish())),
+        )
+    }
+}
+
+/// The cache hits and misses of every [`Cache`] in this process, as `(hits, misses)`.
+pub(crate) fn cache_stats() -> (usize, usize) {
+    (HITS.load(Ordering::Relaxed), MISSES.load(Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, time::Duration};
+
+    use super::{cache_stats, Cache};
+
+    #[test]
+    fn cached_bodies_expire_and_persist() {
+        let dir = env::temp_dir().join(format!("city_weather_cache_{}", process::id()));
+
+        let (hits, misses) = cache_stats();
+        let cache = Cache::new(Duration::from_secs(60), Some(dir.clone()));
+        assert_eq!(cache.get("london"), None);
+        cache.insert("london", b"[]");
+        assert_eq!(cache.get("london").as_deref(), Some(&b"[]"[..]));
+        // Other tests share the counters, so they only grow by at least this run's.
+        let (new_hits, new_misses) = cache_stats();
+        assert!(new_hits > hits && new_misses > misses);
+
+        let next_run = Cache::new(Duration::from_secs(60), Some(dir.clone()));
+        assert_eq!(next_run.get("london").as_deref(), Some(&b"[]"[..]));
+
+        let expired = Cache::new(Duration::from_nanos(1), Some(dir.clone()));
+        assert_eq!(expired.get("london"), None);
+
+        let mut disabled = Cache::new(Duration::ZERO, None);
+        disabled.insert("london", b"[]");
+        assert_eq!(disabled.get("london"), None);
+        disabled.seed("london".to_owned(), b"{}".to_vec());
+        assert_eq!(disabled.get("london").as_deref(), Some(&b"{}"[..]));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
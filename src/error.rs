@@ -0,0 +1,73 @@
+use std::{error::Error, time::Duration};
+
+type Source = Box<dyn Error + Send + Sync>;
+
+/// The ways fetching the weather can fail, so that callers can tell e.g. a rejected API
+/// key from an unreachable service. The `Display` text is the message shown to users.
+#[derive(Debug, thiserror::Error)]
+pub enum WeatherError {
+    /// The service rejected the API key.
+    #[error("Invalid API key for weather service. Please check the configuration.")]
+    InvalidApiKey,
+
+    /// The service kept rejecting requests for exceeding the rate limit of the API key,
+    /// suggesting to wait `retry_after` when it sent a `Retry-After` header.
+    #[error("The weather service rate limit was exceeded{}.", retry_hint(.retry_after))]
+    RateLimited { retry_after: Option<Duration> },
+
+    /// The service could not be reached or kept failing, or a request was not sent.
+    #[error("{message}")]
+    Network {
+        message: String,
+        #[source]
+        source: Option<Source>,
+    },
+
+    /// A response was not the JSON the endpoint is documented to return.
+    #[error("Failed to parse JSON response.")]
+    Parse(#[source] serde_json::Error),
+
+    /// The query resolved to no usable location.
+    #[error("{0}")]
+    NoResults(String),
+
+    /// The query matches places in more countries than allowed.
+    #[error("{0}")]
+    Ambiguous(String),
+
+    /// The configuration, or a file it points to, could not be read or is invalid.
+    #[error("{message}")]
+    Config {
+        message: String,
+        #[source]
+        source: Option<Source>,
+    },
+}
+
+impl WeatherError {
+    pub(crate) fn network(message: impl Into<String>, source: impl Into<Source>) -> Self {
+        WeatherError::Network {
+            message: message.into(),
+            source: Some(source.into()),
+        }
+    }
+
+    pub(crate) fn config(message: impl Into<String>, source: impl Into<Source>) -> Self {
+        WeatherError::Config {
+            message: message.into(),
+            source: Some(source.into()),
+        }
+    }
+}
+
+fn retry_hint(retry_after: &Option<Duration>) -> String {
+    retry_after
+        .map(|wait| format!(", retry after {}s", wait.as_secs()))
+        .unwrap_or_default()
+}
+
+impl From<reqwest::Error> for WeatherError {
+    fn from(error: reqwest::Error) -> Self {
+        WeatherError::network("The request to the weather service failed.", error)
+    }
+}
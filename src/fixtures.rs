@@ -0,0 +1,93 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::WeatherError;
+
+/// Canned responses by city name, answering requests instead of the weather service:
+/// `{ "London": { "geocode": [..], "weather": {..}, "forecast": {..} } }`. Every
+/// geocoding match of a city gets the weather and forecast of that city.
+#[derive(Deserialize)]
+pub(crate) struct Fixtures(HashMap<String, CityFixture>);
+
+#[derive(Deserialize)]
+struct CityFixture {
+    geocode: Vec<Value>,
+    weather: Option<Value>,
+    forecast: Option<Value>,
+}
+
+impl Fixtures {
+    pub(crate) fn load(path: &Path) -> Result<Self, WeatherError> {
+        let content = fs::read(path).map_err(|error| {
+            WeatherError::config(format!("Failed to read {}.", path.display()), error)
+        })?;
+
+        serde_json::from_slice(&content).map_err(|error| {
+            WeatherError::config(format!("Failed to parse {}.", path.display()), error)
+        })
+    }
+
+    /// The body the `identifier` request to `url` gets. Unknown cities match nothing,
+    /// like they would with the service.
+    pub(crate) fn response(&self, identifier: &str, url: &Url) -> Result<Vec<u8>, WeatherError> {
+        let parameter = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+
+        let body = match identifier {
+            "city_location" => {
+                let city = parameter("q").unwrap_or_default();
+                let geocode = self.city(&city).map(|fixture| fixture.geocode.clone());
+                Some(Value::Array(geocode.unwrap_or_default()))
+            }
+            "city_weather" | "city_forecast" => {
+                let coordinate = |name| parameter(name).and_then(|value| value.parse::<f64>().ok());
+                let fixture = coordinate("lat")
+                    .zip(coordinate("lon"))
+                    .and_then(|(lat, lon)| self.city_at(lat, lon));
+
+                match identifier {
+                    "city_weather" => fixture.and_then(|fixture| fixture.weather.clone()),
+                    _ => fixture.and_then(|fixture| fixture.forecast.clone()),
+                }
+            }
+            _ => None,
+        };
+
+        let body = body.ok_or_else(|| {
+            WeatherError::NoResults(format!(
+                "No fixture for the {} request to {}.",
+                identifier,
+                url.query().unwrap_or_default()
+            ))
+        })?;
+
+        Ok(body.to_string().into_bytes())
+    }
+
+    /// The fixture of `city`, ignoring case and any `,STATE,COUNTRY` suffix.
+    fn city(&self, city: &str) -> Option<&CityFixture> {
+        let find = |name: &str| {
+            self.0
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name.trim()))
+                .map(|(_, fixture)| fixture)
+        };
+
+        find(city).or_else(|| find(city.split(',').next().unwrap_or_default()))
+    }
+
+    /// The fixture with a geocoding match at exactly these coordinates.
+    fn city_at(&self, lat: f64, lon: f64) -> Option<&CityFixture> {
+        self.0.values().find(|fixture| {
+            fixture.geocode.iter().any(|location| {
+                location["lat"].as_f64() == Some(lat) && location["lon"].as_f64() == Some(lon)
+            })
+        })
+    }
+}
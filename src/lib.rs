@@ -1,4 +1,9 @@
+mod cache;
+mod fixtures;
+mod seed;
 mod utils;
 
+pub mod aliases;
 pub mod app;
+pub mod error;
 pub mod weather_client;
@@ -1,44 +1,520 @@
-use clap::Parser;
-use tracing::Level;
+use std::{error::Error, fs::File, path::PathBuf, process::ExitCode};
+
+use clap::{Parser, Subcommand};
+use tracing::{warn, Level};
 use weather::{
-    app::{self, OutputType},
-    weather_client,
+    aliases::Aliases,
+    app::{self, ColorChoice, OutputType, PrintOptions, SortKey, TemperatureSpacing},
+    weather_client::{self, Units},
 };
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Config {
-    /// The city name to retrieve the weather information.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// The config file to use instead of `config.toml` in the working directory or the
+    /// platform config dir.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// The city name to retrieve the weather information; repeat it to show several
+    /// cities in one output.
     #[arg(short, long)]
-    pub city: Option<String>,
+    pub city: Vec<String>,
 
     /// The type of output to display the weather information.
     #[arg(short, long)]
     pub output: Option<OutputType>,
+
+    /// The units to display temperatures in; `auto` picks imperial or metric per country.
+    #[arg(short, long)]
+    pub units: Option<Units>,
+
+    /// The units to display temperatures in instead of `--units`.
+    #[arg(long)]
+    pub temp_units: Option<Units>,
+
+    /// The units to display wind speeds in instead of `--units`.
+    #[arg(long)]
+    pub wind_units: Option<Units>,
+
+    /// The language of weather descriptions and place names for this run, e.g. `en`;
+    /// unsupported codes are ignored with a warning.
+    #[arg(long)]
+    pub lang: Option<String>,
+
+    /// Only show the first geocoding match, skipping the sorting and dedup.
+    #[arg(long)]
+    pub first_match: bool,
+
+    /// Keep one location per country and state, even when a city matches several
+    /// distinct places there.
+    #[arg(long)]
+    pub unique_regions: bool,
+
+    /// Only show locations in this country, as an ISO 3166 two-letter code such as `GB`.
+    #[arg(long)]
+    pub country: Option<String>,
+
+    /// Show at most LIMIT of the locations a city matches; 0 means unlimited.
+    #[arg(long)]
+    pub limit: Option<usize>,
+
+    /// Fail when a city matches places in more than MAX_COUNTRIES countries (1 if omitted),
+    /// asking for a country code such as `London,GB` instead.
+    #[arg(long, value_name = "MAX_COUNTRIES", num_args = 0..=1, default_missing_value = "1")]
+    pub require_country_for_ambiguous: Option<usize>,
+
+    /// Never call the weather service; lookups that would need it fail instead.
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Parse the weather response from this file instead of fetching it.
+    #[arg(long, hide = true, value_name = "PATH")]
+    pub replay_weather: Option<PathBuf>,
+
+    /// Parse the geocoding response from this file instead of fetching it.
+    #[arg(long, hide = true, value_name = "PATH")]
+    pub replay_geocode: Option<PathBuf>,
+
+    /// Answer every query from this fixtures file instead of the weather service, mapping
+    /// city names to canned responses.
+    #[arg(long, value_name = "PATH")]
+    pub fixtures: Option<PathBuf>,
+
+    /// Warm the cache with the results in this file: `--output json` output with the `lat`
+    /// and `lon` of each result added. Together with `--offline` it makes runs deterministic.
+    #[arg(long, value_name = "PATH")]
+    pub seed_cache_from: Option<PathBuf>,
+
+    /// Round coordinates to this many decimals in cache keys (4, about 11 m, if omitted);
+    /// fewer decimals let lookups of nearby points share cached responses.
+    #[arg(long, value_name = "DECIMALS")]
+    pub round_coords_for_cache: Option<usize>,
+
+    /// Skip checking the API key and the connection before the first query.
+    #[arg(long)]
+    pub skip_connect_check: bool,
+
+    /// A file with one city name per line to retrieve the weather for, or `-` for stdin.
+    #[arg(long, conflicts_with = "city")]
+    pub cities_file: Option<PathBuf>,
+
+    /// The maximum number of cities processed from `--cities-file`.
+    #[arg(long, default_value_t = 100)]
+    pub max_cities_from_file: usize,
+
+    /// Process every city from `--cities-file`, ignoring `--max-cities-from-file`.
+    #[arg(long)]
+    pub no_limit: bool,
+
+    /// Credit the weather data provider after the results.
+    #[arg(long)]
+    pub attribution: bool,
+
+    /// The number of decimal places shown for coordinates; JSON, CSV and TSV keep full precision.
+    #[arg(long, default_value_t = 4)]
+    pub precision_coords: usize,
+
+    /// Emit each JSON result as a flat object with underscore-joined keys (e.g. `wind_speed`).
+    #[arg(long)]
+    pub json_flat: bool,
+
+    /// Emit temperatures in JSON as bare numbers instead of `{ "value", "unit" }` objects.
+    #[arg(long)]
+    pub json_legacy: bool,
+
+    /// Keep the table output even when it is wider than the terminal.
+    #[arg(long)]
+    pub force_table: bool,
+
+    /// Omit the header row from TSV and CSV output.
+    #[arg(long)]
+    pub no_header: bool,
+
+    /// Show two-letter US state codes as full state names; JSON keeps the raw value.
+    #[arg(long)]
+    pub expand_abbreviations: bool,
+
+    /// Show the five-day forecast, summarized per day, instead of the current weather.
+    #[arg(long)]
+    pub forecast: bool,
+
+    /// Print only the number of results, their temperature range and condition counts.
+    #[arg(long)]
+    pub summary_only: bool,
+
+    /// Where to put spaces in temperatures: `12°C`, `12 °C` or `12° C`. Defaults to
+    /// `temperature_spacing` in the config file, or compact.
+    #[arg(long, value_enum)]
+    pub temperature_spacing: Option<TemperatureSpacing>,
+
+    /// Write the results to this file, replacing its content, instead of stdout.
+    #[arg(long, value_name = "PATH")]
+    pub output_file: Option<PathBuf>,
+
+    /// Print the number and durations of the requests made, per kind, and the cache hit
+    /// ratio after the results.
+    #[arg(long)]
+    pub timings: bool,
+
+    /// Order the results by country, state, city name or temperature.
+    #[arg(long, value_enum)]
+    pub sort: Option<SortKey>,
+
+    /// Sort in descending order, e.g. the warmest first with `--sort temperature`.
+    #[arg(long, requires = "sort")]
+    pub desc: bool,
+
+    /// When to color the table and simple outputs.
+    #[arg(long, value_enum, default_value_t)]
+    pub color: ColorChoice,
+
+    /// The latitude to retrieve the weather at, from -90 to 90; requires `--lon`.
+    #[arg(long, requires = "lon", allow_negative_numbers = true, conflicts_with_all = ["city", "cities_file"])]
+    pub lat: Option<f64>,
+
+    /// The longitude to retrieve the weather at, from -180 to 180; requires `--lat`.
+    #[arg(long, requires = "lat", allow_negative_numbers = true, conflicts_with_all = ["city", "cities_file"])]
+    pub lon: Option<f64>,
+
+    /// The coordinates to retrieve the weather at as `LAT,LON`, e.g. `51.5,-0.12`.
+    #[arg(long, allow_hyphen_values = true, conflicts_with_all = ["city", "cities_file", "lat", "lon"])]
+    pub coords: Option<String>,
+
+    /// The postal code to retrieve the weather for as `ZIP,COUNTRY`, e.g. `90210,US`; the
+    /// country defaults to `--country`.
+    #[arg(long, conflicts_with_all = ["city", "cities_file", "lat", "lon", "coords"])]
+    pub zip: Option<String>,
+
+    /// A full Plus Code (Open Location Code) to retrieve the weather at, e.g. `9C3XGV4C+XV`.
+    #[cfg(feature = "pluscode")]
+    #[arg(long, conflicts_with_all = ["city", "cities_file", "lat", "lon", "coords", "zip"])]
+    pub pluscode: Option<String>,
+
+    /// A Handlebars template file to render the results with; implies `--output template`.
+    #[cfg(feature = "templates")]
+    #[arg(long)]
+    pub template_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compare the weather of two cities side by side.
+    Diff {
+        /// The first city to compare.
+        first: String,
+
+        /// The second city to compare.
+        second: String,
+    },
+
+    /// Print the coordinates a city resolves to, without fetching its weather.
+    Geocode {
+        /// The city name to resolve.
+        city: String,
+    },
+
+    /// Open the location a city resolves to on OpenStreetMap.
+    #[cfg(feature = "map")]
+    Map {
+        /// The city name to show on the map.
+        city: String,
+    },
+
+    /// Manage short names for cities, e.g. `home` for `Seattle,WA,US`.
+    Alias {
+        #[command(subcommand)]
+        command: AliasCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AliasCommand {
+    /// Add an alias, replacing any existing one with the same name.
+    Add {
+        /// The short name to use in place of the city.
+        name: String,
+
+        /// The city query the alias stands for.
+        city: String,
+    },
+
+    /// List the saved aliases.
+    List,
+
+    /// Remove an alias.
+    Remove {
+        /// The alias to remove.
+        name: String,
+    },
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+
+            let mut source = error.source();
+            while let Some(cause) = source {
+                eprintln!("Caused by: {}", cause);
+                source = cause.source();
+            }
+
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
     let cli_config = Config::parse();
-    let app_config = app::AppConfig::load("config.toml")?;
+
+    if let Some(Command::Alias { command }) = &cli_config.command {
+        return manage_aliases(&mut Aliases::load()?, command);
+    }
+
+    let coordinates = match (cli_config.lat, cli_config.lon, &cli_config.coords) {
+        (Some(lat), Some(lon), _) => Some(app::check_coordinates(lat, lon)?),
+        (_, _, Some(coords)) => Some(app::parse_coordinates(coords)?),
+        _ => None,
+    };
+    #[cfg(feature = "pluscode")]
+    let coordinates = match &cli_config.pluscode {
+        Some(code) => Some(app::decode_plus_code(code)?),
+        None => coordinates,
+    };
+
+    let config_path = cli_config
+        .config
+        .clone()
+        .unwrap_or_else(app::AppConfig::default_path);
+    let mut app_config = app::AppConfig::load(config_path)?;
 
     app::init_tracing(match app_config.level().as_ref() {
         Some(level) => level.parse::<Level>().unwrap_or(Level::INFO),
         None => Level::INFO,
     });
 
-    let output = cli_config
+    // Only managing aliases needs them; a broken file must not stop every other query.
+    let aliases = Aliases::load().unwrap_or_else(|error| {
+        match error.source() {
+            Some(cause) => warn!("ignoring the aliases: {} {}", error, cause),
+            None => warn!("ignoring the aliases: {}", error),
+        }
+        Aliases::default()
+    });
+
+    let max_cities = (!cli_config.no_limit).then_some(cli_config.max_cities_from_file);
+    let cities = cli_config
+        .cities_file
+        .as_ref()
+        .map(|path| app::read_cities(path, max_cities))
+        .transpose()?
+        .map(|cities| {
+            cities
+                .iter()
+                .map(|city| aliases.resolve(city).to_owned())
+                .collect::<Vec<_>>()
+        });
+
+    let output_type = cli_config
         .output
         .unwrap_or_else(|| app_config.output().to_owned());
 
-    let client = weather_client::Client::new(app_config.client)
-        .connect()
-        .await?;
+    #[cfg(feature = "templates")]
+    let (output_type, template) = match &cli_config.template_file {
+        Some(path) => (OutputType::Template, Some(app::load_template(path)?)),
+        None => (output_type, None),
+    };
+
+    let output_file = cli_config
+        .output_file
+        .as_ref()
+        .map(|path| {
+            File::create(path).map_err(|error| {
+                format!(
+                    "Failed to create the output file {}: {}",
+                    path.display(),
+                    error
+                )
+            })
+        })
+        .transpose()?;
+
+    let options = PrintOptions {
+        output_type,
+        attribution: cli_config.attribution,
+        coordinate_precision: cli_config.precision_coords,
+        json_flat: cli_config.json_flat,
+        json_legacy: cli_config.json_legacy,
+        force_table: cli_config.force_table,
+        no_header: cli_config.no_header,
+        expand_abbreviations: cli_config.expand_abbreviations,
+        forecast: cli_config.forecast,
+        summary_only: cli_config.summary_only,
+        temperature_spacing: cli_config
+            .temperature_spacing
+            .unwrap_or(*app_config.temperature_spacing()),
+        color: match &output_file {
+            Some(_) => cli_config.color == ColorChoice::Always,
+            None => cli_config.color.enabled(),
+        },
+        sort: cli_config.sort,
+        descending: cli_config.desc,
+        output_file,
+        #[cfg(feature = "templates")]
+        template,
+    };
+
+    if let Some(units) = cli_config.units {
+        app_config.client.set_units(units);
+    }
+
+    if let Some(units) = cli_config.temp_units {
+        app_config.client.set_temperature_units(Some(units));
+    }
+
+    if let Some(units) = cli_config.wind_units {
+        app_config.client.set_wind_units(Some(units));
+    }
+
+    if let Some(lang) = &cli_config.lang {
+        if weather_client::is_supported_language(lang) {
+            app_config.client.set_lang(lang.to_lowercase());
+        } else {
+            warn!(
+                "unsupported language \"{}\", using \"{}\" from the config",
+                lang,
+                app_config.client.lang()
+            );
+        }
+    }
+
+    if cli_config.first_match {
+        app_config.client.set_first_match(true);
+    }
+
+    if cli_config.unique_regions {
+        app_config.client.set_unique_regions(true);
+    }
+
+    if cli_config.country.is_some() {
+        app_config.client.set_country(cli_config.country.clone());
+    }
+
+    if let Some(limit) = cli_config.limit {
+        app_config.client.set_limit(Some(limit));
+    }
+
+    if let Some(max_countries) = cli_config.require_country_for_ambiguous {
+        app_config.client.set_max_countries(Some(max_countries));
+    }
+
+    if cli_config.offline {
+        app_config.client.set_offline(true);
+    }
+
+    if cli_config.skip_connect_check {
+        app_config.client.set_skip_connect_check(true);
+    }
+
+    if let Some(precision) = cli_config.round_coords_for_cache {
+        app_config.client.set_cache_coordinate_precision(precision);
+    }
+
+    if cli_config.seed_cache_from.is_some() {
+        app_config
+            .client
+            .set_seed_cache_from(cli_config.seed_cache_from.clone());
+    }
+
+    if cli_config.replay_weather.is_some() {
+        app_config
+            .client
+            .set_replay_weather(cli_config.replay_weather.clone());
+    }
+
+    if cli_config.replay_geocode.is_some() {
+        app_config
+            .client
+            .set_replay_geocode(cli_config.replay_geocode.clone());
+    }
+
+    let client = match &cli_config.fixtures {
+        Some(path) => weather_client::Client::new_mock(app_config.client, path)?,
+        None => {
+            weather_client::Client::new(app_config.client)?
+                .connect()
+                .await?
+        }
+    };
+
+    let result = match &cli_config.command {
+        Some(Command::Diff { first, second }) => {
+            let (first, second) = (aliases.resolve(first), aliases.resolve(second));
+            app::print_city_weather_diff(&client, first, second, &options).await
+        }
+        Some(Command::Geocode { city }) => {
+            app::print_city_locations(&client, aliases.resolve(city), &options).await
+        }
+        #[cfg(feature = "map")]
+        Some(Command::Map { city }) => app::open_city_map(&client, aliases.resolve(city)).await,
+        Some(Command::Alias { .. }) => unreachable!("aliases are managed before connecting"),
+        None => {
+            if let Some((lat, lon)) = coordinates {
+                app::print_weather_at(&client, lat, lon, &options).await
+            } else if let Some(zip) = &cli_config.zip {
+                app::print_zip_weather(&client, zip, &options).await
+            } else if let Some(cities) = &cities {
+                app::print_cities_weather(&client, cities, &options).await
+            } else if let [city] = cli_config.city.as_slice() {
+                app::print_city_weather(&client, aliases.resolve(city), &options).await
+            } else if !cli_config.city.is_empty() {
+                let cities: Vec<String> = cli_config
+                    .city
+                    .iter()
+                    .map(|city| aliases.resolve(city).to_owned())
+                    .collect();
+                app::print_cities_weather(&client, &cities, &options).await
+            } else {
+                app::print_city_weather_interactive(&client, &aliases, &options).await;
+                Ok(())
+            }
+        }
+    };
+
+    if cli_config.timings {
+        app::print_timings();
+    }
+
+    match result {
+        Err(error) if app::is_broken_pipe(error.as_ref()) => Ok(()),
+        result => result,
+    }
+}
+
+fn manage_aliases(aliases: &mut Aliases, command: &AliasCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        AliasCommand::Add { name, city } => {
+            aliases.add(name, city);
+            aliases.save()?;
+        }
+        AliasCommand::List => {
+            for (name, city) in aliases.iter() {
+                println!("{}\t{}", name, city);
+            }
+        }
+        AliasCommand::Remove { name } => {
+            if !aliases.remove(name) {
+                return Err(format!("There is no alias named \"{}\".", name).into());
+            }
 
-    if let Some(city) = &cli_config.city {
-        app::print_city_weather(&client, city, &output).await?;
-    } else {
-        app::print_city_weather_interactive(&client, &output).await;
+            aliases.save()?;
+        }
     }
 
     Ok(())
@@ -0,0 +1,138 @@
+use std::{fs, path::Path};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{error::WeatherError, utils, weather_client::Units};
+
+/// A previously fetched result to warm the cache with: one element of the `--output json`
+/// array, plus the `lat` and `lon` it was fetched for.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Seed {
+    pub(crate) lat: f64,
+    pub(crate) lon: f64,
+    weather: String,
+    pub(crate) country: String,
+    state: Option<String>,
+    pub(crate) city_name: String,
+    temperature: Reading,
+    feels_like: Option<Reading>,
+    humidity: Option<u8>,
+    wind: Option<Wind>,
+    cloudiness: Option<u8>,
+    units: Units,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Reading {
+    value: f64,
+    unit: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Wind {
+    speed: f64,
+    unit: String,
+}
+
+impl Seed {
+    /// Reads the seeds in `path`, rejecting the whole file when any of them does not
+    /// match the schema, so that a typo does not silently leave cities uncached.
+    pub(crate) fn load(path: &Path) -> Result<Vec<Seed>, WeatherError> {
+        let content = fs::read(path).map_err(|error| {
+            WeatherError::config(format!("Failed to read {}.", path.display()), error)
+        })?;
+
+        let seeds: Vec<Seed> = serde_json::from_slice(&content).map_err(|error| {
+            WeatherError::config(format!("Failed to parse {}.", path.display()), error)
+        })?;
+
+        for (index, seed) in seeds.iter().enumerate() {
+            seed.validate().map_err(|problem| WeatherError::Config {
+                message: format!(
+                    "Invalid result {} ({}) in {}: {}.",
+                    index + 1,
+                    seed.city_name,
+                    path.display(),
+                    problem
+                ),
+                source: None,
+            })?;
+        }
+
+        Ok(seeds)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !(-90.0..=90.0).contains(&self.lat) || !(-180.0..=180.0).contains(&self.lon) {
+            return Err(format!("{},{} are not coordinates", self.lat, self.lon));
+        }
+
+        if self.units == Units::Auto {
+            return Err("`units` must be metric, imperial or standard".to_owned());
+        }
+
+        let symbol = self.units.temperature_symbol();
+        for reading in std::iter::once(&self.temperature).chain(&self.feels_like) {
+            if reading.unit != symbol {
+                return Err(format!(
+                    "the temperature unit {} does not match the {} units",
+                    reading.unit, self.units
+                ));
+            }
+        }
+
+        match &self.wind {
+            Some(wind) if wind_units(&wind.unit).is_none() => {
+                Err(format!("unknown wind speed unit {}", wind.unit))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The body the weather service would have sent for this result in `units`.
+    pub(crate) fn weather_body(&self, units: Units) -> Vec<u8> {
+        let temperature = |value| utils::convert_temperature(value, self.units, units);
+        let wind = self.wind.as_ref().and_then(|wind| {
+            let speed = utils::convert_speed(wind.speed, wind_units(&wind.unit)?, units);
+            Some(json!({ "speed": speed }))
+        });
+
+        json!({
+            "weather": [{ "description": self.weather }],
+            "main": {
+                "temp": temperature(self.temperature.value),
+                "feels_like": self.feels_like.as_ref().map(|reading| temperature(reading.value)),
+                "humidity": self.humidity,
+            },
+            "clouds": self.cloudiness.map(|all| json!({ "all": all })),
+            "wind": wind,
+            "sys": { "country": self.country },
+            "name": self.city_name,
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    /// The geocoding match the weather service would have sent for this result.
+    pub(crate) fn location(&self) -> Value {
+        json!({
+            "name": self.city_name,
+            "lat": self.lat,
+            "lon": self.lon,
+            "country": self.country,
+            "state": self.state,
+        })
+    }
+}
+
+fn wind_units(symbol: &str) -> Option<Units> {
+    match symbol {
+        "m/s" => Some(Units::Metric),
+        "mph" => Some(Units::Imperial),
+        _ => None,
+    }
+}
@@ -1,7 +1,17 @@
-use std::time::Instant;
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
 
 use tracing::debug;
 
+use crate::weather_client::Units;
+
+/// The durations of every [`Timing`] so far, by identifier.
+static TIMINGS: Mutex<BTreeMap<&str, Vec<Duration>>> = Mutex::new(BTreeMap::new());
+
+/// Logs how long it lived when dropped, and records it for [`timing_summaries`].
 pub(crate) struct Timing {
     start: Instant,
     identifier: &'static str,
@@ -18,7 +28,289 @@ impl Timing {
 
 impl Drop for Timing {
     fn drop(&mut self) {
-        let elapsed = self.start.elapsed().as_millis();
-        debug!("{}, elapsed: {}ms", self.identifier, elapsed);
+        let elapsed = self.start.elapsed();
+        debug!("{}, elapsed: {}ms", self.identifier, elapsed.as_millis());
+
+        TIMINGS
+            .lock()
+            .expect("timings lock poisoned")
+            .entry(self.identifier)
+            .or_default()
+            .push(elapsed);
+    }
+}
+
+/// The recorded durations of one [`Timing`] identifier.
+pub(crate) struct TimingSummary {
+    pub(crate) identifier: &'static str,
+    pub(crate) count: usize,
+    pub(crate) total: Duration,
+    pub(crate) min: Duration,
+    pub(crate) max: Duration,
+}
+
+impl TimingSummary {
+    pub(crate) fn average(&self) -> Duration {
+        self.total / self.count as u32
+    }
+}
+
+/// Summarizes the durations recorded so far, ordered by identifier.
+pub(crate) fn timing_summaries() -> Vec<TimingSummary> {
+    TIMINGS
+        .lock()
+        .expect("timings lock poisoned")
+        .iter()
+        .filter(|(_, durations)| !durations.is_empty())
+        .map(|(identifier, durations)| TimingSummary {
+            identifier,
+            count: durations.len(),
+            total: durations.iter().sum(),
+            min: durations.iter().copied().min().unwrap_or_default(),
+            max: durations.iter().copied().max().unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Converts a temperature between unit systems, rounded to the two decimals the weather
+/// service sends; a temperature already in `to` is returned as it is.
+pub(crate) fn convert_temperature(value: f64, from: Units, to: Units) -> f64 {
+    let (from, to) = (from.resolve(""), to.resolve(""));
+    if from == to {
+        return value;
+    }
+
+    let celsius = match from {
+        Units::Imperial => (value - 32.0) * 5.0 / 9.0,
+        Units::Standard => value - 273.15,
+        Units::Metric | Units::Auto => value,
+    };
+
+    round_reading(match to {
+        Units::Imperial => celsius * 9.0 / 5.0 + 32.0,
+        Units::Standard => celsius + 273.15,
+        Units::Metric | Units::Auto => celsius,
+    })
+}
+
+const METERS_PER_SECOND_PER_MPH: f64 = 0.447_04;
+
+/// Converts a wind speed between unit systems like [`convert_temperature`]; metric and
+/// standard speeds are both in meters per second.
+pub(crate) fn convert_speed(value: f64, from: Units, to: Units) -> f64 {
+    let imperial = |units: Units| units == Units::Imperial;
+    if imperial(from) == imperial(to) {
+        return value;
+    }
+
+    round_reading(match imperial(to) {
+        true => value / METERS_PER_SECOND_PER_MPH,
+        false => value * METERS_PER_SECOND_PER_MPH,
+    })
+}
+
+fn round_reading(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Parses an HTTP date in the preferred format, as in `Sun, 06 Nov 1994 08:49:37 GMT`.
+/// The obsolete RFC 850 and asctime formats are not accepted.
+pub(crate) fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let [weekday, day, month, year, time, "GMT"] = value.split_whitespace().collect::<Vec<_>>()[..]
+    else {
+        return None;
+    };
+
+    if !weekday.ends_with(',') {
+        return None;
+    }
+
+    let day: i64 = day.parse().ok().filter(|day| (1..=31).contains(day))?;
+    let month = MONTHS.iter().position(|known| *known == month)? as i64 + 1;
+    let year: i64 = year.parse().ok()?;
+
+    let [hours, minutes, seconds] = time
+        .split(':')
+        .map(|part| part.parse::<i64>().ok())
+        .collect::<Option<Vec<_>>>()?[..]
+    else {
+        return None;
+    };
+
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+
+    let secs = days_from_civil(year, month, day) * 86_400 + hours * 3_600 + minutes * 60 + seconds;
+
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(u64::try_from(secs).ok()?))
+}
+
+/// The number of days from 1970-01-01 to the given date of the proleptic Gregorian
+/// calendar, after Howard Hinnant's `days_from_civil`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+const US_STATES: [(&str, &str); 56] = [
+    ("AL", "Alabama"),
+    ("AK", "Alaska"),
+    ("AS", "American Samoa"),
+    ("AZ", "Arizona"),
+    ("AR", "Arkansas"),
+    ("CA", "California"),
+    ("CO", "Colorado"),
+    ("CT", "Connecticut"),
+    ("DE", "Delaware"),
+    ("DC", "District of Columbia"),
+    ("FL", "Florida"),
+    ("GA", "Georgia"),
+    ("GU", "Guam"),
+    ("HI", "Hawaii"),
+    ("ID", "Idaho"),
+    ("IL", "Illinois"),
+    ("IN", "Indiana"),
+    ("IA", "Iowa"),
+    ("KS", "Kansas"),
+    ("KY", "Kentucky"),
+    ("LA", "Louisiana"),
+    ("ME", "Maine"),
+    ("MD", "Maryland"),
+    ("MA", "Massachusetts"),
+    ("MI", "Michigan"),
+    ("MN", "Minnesota"),
+    ("MS", "Mississippi"),
+    ("MO", "Missouri"),
+    ("MT", "Montana"),
+    ("NE", "Nebraska"),
+    ("NV", "Nevada"),
+    ("NH", "New Hampshire"),
+    ("NJ", "New Jersey"),
+    ("NM", "New Mexico"),
+    ("NY", "New York"),
+    ("NC", "North Carolina"),
+    ("ND", "North Dakota"),
+    ("MP", "Northern Mariana Islands"),
+    ("OH", "Ohio"),
+    ("OK", "Oklahoma"),
+    ("OR", "Oregon"),
+    ("PA", "Pennsylvania"),
+    ("PR", "Puerto Rico"),
+    ("RI", "Rhode Island"),
+    ("SC", "South Carolina"),
+    ("SD", "South Dakota"),
+    ("TN", "Tennessee"),
+    ("TX", "Texas"),
+    ("UT", "Utah"),
+    ("VT", "Vermont"),
+    ("VI", "U.S. Virgin Islands"),
+    ("VA", "Virginia"),
+    ("WA", "Washington"),
+    ("WV", "West Virginia"),
+    ("WI", "Wisconsin"),
+    ("WY", "Wyoming"),
+];
+
+/// The full name of the US state, district or territory with the given two-letter
+/// postal code, matched case-insensitively.
+pub(crate) fn us_state_name(code: &str) -> Option<&'static str> {
+    US_STATES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+        .map(|(_, name)| *name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{
+        convert_speed, convert_temperature, parse_http_date, timing_summaries, us_state_name,
+        Timing,
+    };
+    use crate::weather_client::Units;
+
+    #[test]
+    fn temperature_conversions() {
+        let pairs = [
+            (Units::Metric, Units::Imperial, 20.0, 68.0),
+            (Units::Metric, Units::Standard, 20.0, 293.15),
+            (Units::Imperial, Units::Metric, 68.0, 20.0),
+            (Units::Imperial, Units::Standard, 32.0, 273.15),
+            (Units::Standard, Units::Metric, 273.15, 0.0),
+            (Units::Standard, Units::Imperial, 373.15, 212.0),
+            (Units::Auto, Units::Metric, 12.345, 12.345),
+        ];
+
+        for (from, to, value, converted) in pairs {
+            assert_eq!(
+                convert_temperature(value, from, to),
+                converted,
+                "{} to {}",
+                from,
+                to
+            );
+        }
+    }
+
+    #[test]
+    fn speed_conversions() {
+        assert_eq!(convert_speed(10.0, Units::Metric, Units::Imperial), 22.37);
+        assert_eq!(convert_speed(10.0, Units::Standard, Units::Imperial), 22.37);
+        assert_eq!(convert_speed(22.37, Units::Imperial, Units::Metric), 10.0);
+        assert_eq!(convert_speed(22.37, Units::Imperial, Units::Standard), 10.0);
+        assert_eq!(convert_speed(3.333, Units::Standard, Units::Metric), 3.333);
+        assert_eq!(
+            convert_speed(3.333, Units::Imperial, Units::Imperial),
+            3.333
+        );
+    }
+
+    #[test]
+    fn http_dates() {
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777))
+        );
+        assert_eq!(
+            parse_http_date("Thu, 29 Feb 2024 00:00:00 GMT"),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_709_164_800))
+        );
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 CET"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 24:00:00 GMT"), None);
+        assert_eq!(parse_http_date("120"), None);
+    }
+
+    #[test]
+    fn us_state_names() {
+        assert_eq!(us_state_name("WA"), Some("Washington"));
+        assert_eq!(us_state_name("ny"), Some("New York"));
+        assert_eq!(us_state_name("Washington"), None);
+        assert_eq!(us_state_name(""), None);
+    }
+
+    #[test]
+    fn timings_are_summarized() {
+        drop(Timing::new("test_timing"));
+        drop(Timing::new("test_timing"));
+
+        let summaries = timing_summaries();
+        let summary = summaries
+            .iter()
+            .find(|summary| summary.identifier == "test_timing")
+            .unwrap();
+        assert_eq!(summary.count, 2);
+        assert!(summary.min <= summary.average() && summary.average() <= summary.max);
     }
 }
@@ -1,22 +1,295 @@
-use anyhow::{bail, Context, Error, Ok};
-use getset::Getters;
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use clap::ValueEnum;
+use futures_util::{future, stream, StreamExt};
+use getset::{Getters, Setters};
 use itertools::Itertools;
-use reqwest::{Client as HttpClient, ClientBuilder, IntoUrl, StatusCode};
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use tracing::warn;
+use reqwest::{redirect::Policy, Client as HttpClient, ClientBuilder, IntoUrl, StatusCode, Url};
+use serde::{de::DeserializeOwned, ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use tracing::{debug, warn};
+
+use crate::{
+    cache::Cache,
+    error::WeatherError,
+    fixtures::Fixtures,
+    seed::Seed,
+    utils::{self, Timing},
+};
+
+/// Credit line for the service the weather data comes from.
+pub const ATTRIBUTION: &str = "Weather data by OpenWeatherMap";
+
+/// Countries where temperatures are commonly given in Fahrenheit: the United States
+/// and its territories (Puerto Rico, Guam, U.S. Virgin Islands, American Samoa and
+/// the Northern Mariana Islands), the Bahamas, Belize, the Cayman Islands, Liberia,
+/// Palau, Micronesia and the Marshall Islands.
+const IMPERIAL_COUNTRIES: &[&str] = &[
+    "US", "PR", "GU", "VI", "AS", "MP", "BS", "BZ", "KY", "LR", "PW", "FM", "MH",
+];
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+    /// Kelvin, the service's default.
+    Standard,
+    /// Imperial for Fahrenheit-using countries, metric elsewhere.
+    Auto,
+}
+
+impl Units {
+    /// Resolves [`Units::Auto`] to a concrete unit system for the given country code.
+    pub fn resolve(self, country: &str) -> Units {
+        match self {
+            Units::Auto if IMPERIAL_COUNTRIES.contains(&country) => Units::Imperial,
+            Units::Auto => Units::Metric,
+            units => units,
+        }
+    }
+
+    /// The letter temperatures in these units are suffixed with, as in `°C`.
+    pub fn temperature_symbol(self) -> &'static str {
+        match self {
+            Units::Imperial => "F",
+            Units::Standard => "K",
+            Units::Metric | Units::Auto => "C",
+        }
+    }
+
+    /// The unit wind speeds in these units are given in.
+    pub fn speed_symbol(self) -> &'static str {
+        match self {
+            Units::Imperial => "mph",
+            Units::Metric | Units::Standard | Units::Auto => "m/s",
+        }
+    }
+
+    /// The degree sign before [`Units::temperature_symbol`]; kelvins go without one.
+    pub fn degree_sign(self) -> &'static str {
+        match self {
+            Units::Standard => "",
+            Units::Metric | Units::Imperial | Units::Auto => "°",
+        }
+    }
+
+    fn query_value(self) -> &'static str {
+        match self {
+            Units::Imperial => "imperial",
+            Units::Standard => "standard",
+            Units::Metric | Units::Auto => "metric",
+        }
+    }
+}
 
-use crate::utils::Timing;
+impl fmt::Display for Units {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Units::Metric => "metric",
+            Units::Imperial => "imperial",
+            Units::Standard => "standard",
+            Units::Auto => "auto",
+        })
+    }
+}
 
-#[derive(Deserialize, Getters)]
+#[derive(Deserialize, Getters, Setters)]
 pub struct Config {
-    #[getset(get = "pub")]
+    /// Empty when the config file leaves the key to the environment, see
+    /// [`crate::app::AppConfig::load`].
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
     api_key: String,
 
-    #[getset(get = "pub")]
+    /// The language of weather descriptions and, where the geocoding service knows
+    /// one, of place names; see [`SUPPORTED_LANGUAGES`].
+    #[getset(get = "pub", set = "pub")]
     lang: String,
+
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    units: Units,
+
+    /// The units temperatures are shown in instead of `units`. Results are then fetched
+    /// in standard units and converted.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    temperature_units: Option<Units>,
+
+    /// The units wind speeds are shown in instead of `units`, converted like
+    /// `temperature_units`; metric and standard both mean meters per second.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    wind_units: Option<Units>,
+
+    /// Return only the first geocoding match, as ranked by the service.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    first_match: bool,
+
+    /// Reject queries whose matches span more than this many countries, asking for a
+    /// country code instead of returning a large mixed result set.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    max_countries: Option<usize>,
+
+    /// Fail every request that would reach the network instead of sending it.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    offline: bool,
+
+    /// Read weather responses from this file instead of the network, for replaying a
+    /// captured response when debugging.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    replay_weather: Option<PathBuf>,
+
+    /// Read geocoding responses from this file instead of the network.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    replay_geocode: Option<PathBuf>,
+
+    /// The maximum number of weather requests in flight for a single city query.
+    #[serde(default = "default_concurrency")]
+    #[getset(get = "pub", set = "pub")]
+    concurrency: usize,
+
+    /// The time in seconds a whole request may take before it fails.
+    #[serde(default = "default_timeout_secs")]
+    #[getset(get = "pub", set = "pub")]
+    timeout_secs: u64,
+
+    /// The time in seconds connecting to the service may take before a request fails.
+    #[serde(default = "default_connect_timeout_secs")]
+    #[getset(get = "pub", set = "pub")]
+    connect_timeout_secs: u64,
+
+    /// How often a request is retried after a transient failure: rate limiting, a
+    /// server error or a connection problem.
+    #[serde(default = "default_max_retries")]
+    #[getset(get = "pub", set = "pub")]
+    max_retries: u32,
+
+    /// How many retries all requests of the run may make together, so that an outage
+    /// does not multiply into `max_retries` extra calls per request. Once spent, failing
+    /// requests are not retried anymore; absent means unlimited.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    max_total_retries: Option<u32>,
+
+    /// The delay before the first retry in milliseconds, doubled for every further one.
+    #[serde(default = "default_base_backoff_ms")]
+    #[getset(get = "pub", set = "pub")]
+    base_backoff_ms: u64,
+
+    /// Rate limits that last longer than this many seconds are reported rather than
+    /// waited out.
+    #[serde(default = "default_max_retry_after_secs")]
+    #[getset(get = "pub", set = "pub")]
+    max_retry_after_secs: u64,
+
+    /// The scheme and host the endpoints are requested from, e.g. to go through a
+    /// proxy or to talk to a mock server in tests.
+    #[serde(default = "default_base_url")]
+    #[getset(get = "pub", set = "pub")]
+    base_url: String,
+
+    /// The maximum number of locations a query resolves to, after sorting and dedup;
+    /// `0` or absent means unlimited.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    limit: Option<usize>,
+
+    /// Only keep the locations in this country, given as an ISO 3166 two-letter code.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    country: Option<String>,
+
+    /// Do not check the key and the connection in `connect`; the first query fails
+    /// instead when either is broken.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    skip_connect_check: bool,
+
+    /// Keep one location per country and state, instead of merging only the matches
+    /// that are the same place.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    unique_regions: bool,
+
+    /// How long in seconds a response is reused for the same request, i.e. the same
+    /// city, units and language; `0` or absent disables caching.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    cache_ttl_secs: u64,
+
+    /// Also keep cached responses in this directory, so that they outlive the process.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    cache_dir: Option<PathBuf>,
+
+    /// The decimals coordinates are rounded to in cache keys, so that nearby points share
+    /// cached responses. At the equator a difference in the last one is about 111 km for
+    /// 0 decimals, 11 km for 1, 1.1 km for 2, 110 m for 3, 11 m for 4 and 1.1 m for 5;
+    /// less towards the poles for longitudes.
+    #[serde(default = "default_cache_coordinate_precision")]
+    #[getset(get = "pub", set = "pub")]
+    cache_coordinate_precision: usize,
+
+    /// Warm the cache with the results in this file before the first query, so that their
+    /// city names and coordinates are answered without the weather service. The file is
+    /// the `--output json` array with the `lat` and `lon` of each result added.
+    #[serde(default)]
+    #[getset(get = "pub", set = "pub")]
+    seed_cache_from: Option<PathBuf>,
 }
 
-#[derive(Debug, Getters, Serialize)]
+fn default_concurrency() -> usize {
+    8
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    5
+}
+
+fn default_max_retries() -> u32 {
+    2
+}
+
+fn default_base_backoff_ms() -> u64 {
+    500
+}
+
+fn default_max_retry_after_secs() -> u64 {
+    60
+}
+
+fn default_cache_coordinate_precision() -> usize {
+    4
+}
+
+fn default_base_url() -> String {
+    "https://api.openweathermap.org".to_owned()
+}
+
+/// The weather at one location.
+///
+/// In its serialized form the temperature carries its unit, e.g.
+/// `"temperature": { "value": 12.3, "unit": "C" }`, and so does the wind speed,
+/// alongside the `units` system of the temperatures.
+#[derive(Debug, Getters)]
 pub struct CityWeather {
     #[getset(get = "pub")]
     weather: String,
@@ -31,7 +304,217 @@ pub struct CityWeather {
     city_name: String,
 
     #[getset(get = "pub")]
-    temperature: f32,
+    temperature: f64,
+
+    /// Cloudiness in percent, when reported.
+    #[getset(get = "pub")]
+    cloudiness: Option<u8>,
+
+    #[getset(get = "pub")]
+    units: Units,
+
+    /// The perceived temperature, when reported.
+    #[getset(get = "pub")]
+    feels_like: Option<f64>,
+
+    /// Relative humidity in percent, when reported.
+    #[getset(get = "pub")]
+    humidity: Option<u8>,
+
+    /// Wind speed in the [`Units::speed_symbol`] of `wind_units`, when reported.
+    #[getset(get = "pub")]
+    wind_speed: Option<f64>,
+
+    /// The units of the wind speed, which differ from `units` with
+    /// [`Config::wind_units`].
+    #[getset(get = "pub")]
+    wind_units: Units,
+}
+
+#[derive(Serialize)]
+struct Temperature {
+    value: f64,
+    unit: &'static str,
+}
+
+#[derive(Serialize)]
+struct Wind {
+    speed: f64,
+    unit: &'static str,
+}
+
+impl Serialize for CityWeather {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let temperature = |value| Temperature {
+            value,
+            unit: self.units.temperature_symbol(),
+        };
+
+        let mut state = serializer.serialize_struct("CityWeather", 10)?;
+        state.serialize_field("weather", &self.weather)?;
+        state.serialize_field("country", &self.country)?;
+        state.serialize_field("state", &self.state)?;
+        state.serialize_field("city_name", &self.city_name)?;
+        state.serialize_field("temperature", &temperature(self.temperature))?;
+        state.serialize_field("feels_like", &self.feels_like.map(temperature))?;
+        state.serialize_field("humidity", &self.humidity)?;
+        let wind = self.wind_speed.map(|speed| Wind {
+            speed,
+            unit: self.wind_units.speed_symbol(),
+        });
+        state.serialize_field("wind", &wind)?;
+        state.serialize_field("cloudiness", &self.cloudiness)?;
+        state.serialize_field("units", &self.units)?;
+        state.end()
+    }
+}
+
+/// The geocoding endpoint answers with an error object instead of the list of
+/// locations when it rejects a query.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum GeocodingResponse {
+    Locations(Vec<CityLocation>),
+    Error { message: String },
+}
+
+impl GeocodingResponse {
+    fn into_locations(self) -> Result<Vec<CityLocation>, WeatherError> {
+        match self {
+            GeocodingResponse::Locations(locations) => Ok(locations),
+            GeocodingResponse::Error { message } => Err(WeatherError::NoResults(format!(
+                "Geocoding failed: {}",
+                message
+            ))),
+        }
+    }
+}
+
+/// The ZIP code endpoint answers with a single location, or an error object when it
+/// does not know the code.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ZipResponse {
+    Location(CityLocation),
+    Error { message: String },
+}
+
+impl CityWeather {
+    /// The weather of a `response` fetched in `fetched` units, with the temperatures and
+    /// wind speed converted to the units the config shows them in for `country`.
+    fn new(
+        mut response: WeatherResponse,
+        fetched: Units,
+        config: &Config,
+        city_name: String,
+        country: String,
+        state: Option<String>,
+    ) -> Option<Self> {
+        let (units, wind_units) = config.shown_units(&country);
+        response.convert(fetched, units, wind_units);
+
+        response
+            .weather
+            .into_iter()
+            .next()
+            .map(|weather| CityWeather {
+                weather: weather.description,
+                temperature: response.main.temp,
+                cloudiness: response.clouds.map(|clouds| clouds.all),
+                units,
+                feels_like: response.main.feels_like,
+                humidity: response.main.humidity,
+                wind_speed: response.wind.and_then(|wind| wind.speed),
+                wind_units,
+                country,
+                city_name,
+                state,
+            })
+    }
+}
+
+/// The forecast for one location, summarized per day.
+#[derive(Debug, Getters, Serialize)]
+pub struct CityForecast {
+    #[getset(get = "pub")]
+    city_name: String,
+
+    #[getset(get = "pub")]
+    country: String,
+
+    #[getset(get = "pub")]
+    state: Option<String>,
+
+    #[getset(get = "pub")]
+    units: Units,
+
+    #[getset(get = "pub")]
+    days: Vec<DailyForecast>,
+}
+
+/// One day of a [`CityForecast`], aggregated from the 3-hour intervals of the
+/// forecast endpoint.
+#[derive(Debug, Getters, Serialize)]
+pub struct DailyForecast {
+    /// The UTC date, as `YYYY-MM-DD`.
+    #[getset(get = "pub")]
+    date: String,
+
+    /// The most frequent weather description among the day's intervals.
+    #[getset(get = "pub")]
+    weather: String,
+
+    #[getset(get = "pub")]
+    min_temperature: f64,
+
+    #[getset(get = "pub")]
+    max_temperature: f64,
+
+    /// The number of intervals the day was aggregated from. A full day has 8; the first
+    /// and last days of a forecast usually have fewer.
+    #[getset(get = "pub")]
+    intervals: usize,
+}
+
+impl DailyForecast {
+    /// Groups the intervals, which the service returns in chronological order, by day.
+    fn from_intervals(intervals: Vec<ForecastInterval>) -> Vec<Self> {
+        intervals
+            .into_iter()
+            .chunk_by(|interval| interval.date().to_owned())
+            .into_iter()
+            .map(|(date, day)| DailyForecast::from_day(date, day.collect()))
+            .collect()
+    }
+
+    fn from_day(date: String, intervals: Vec<ForecastInterval>) -> Self {
+        let descriptions = intervals
+            .iter()
+            .filter_map(|interval| interval.weather.first())
+            .map(|weather| weather.description.as_str());
+        let counts = descriptions.clone().counts();
+        // `max_by_key` picks the last of equally frequent descriptions, so iterate in
+        // reverse to break ties in favor of the earliest one.
+        let weather = descriptions
+            .rev()
+            .max_by_key(|description| counts[description])
+            .unwrap_or_default()
+            .to_owned();
+
+        DailyForecast {
+            date,
+            weather,
+            min_temperature: intervals
+                .iter()
+                .map(|interval| interval.main.temp_min)
+                .fold(f64::INFINITY, f64::min),
+            max_temperature: intervals
+                .iter()
+                .map(|interval| interval.main.temp_max)
+                .fold(f64::NEG_INFINITY, f64::max),
+            intervals: intervals.len(),
+        }
+    }
 }
 
 pub struct Offline;
@@ -41,6 +524,10 @@ pub struct Connected;
 pub struct Client<T = Offline> {
     config: Config,
     client: HttpClient,
+    cache: Cache,
+    fixtures: Option<Fixtures>,
+    /// The retries made so far, against [`Config::max_total_retries`].
+    retries: AtomicU32,
     _state: std::marker::PhantomData<T>,
 }
 
@@ -48,6 +535,27 @@ pub struct Client<T = Offline> {
 struct WeatherResponse {
     weather: Vec<Weather>,
     main: MainWeather,
+    clouds: Option<Clouds>,
+    wind: Option<WindResponse>,
+    sys: Option<Sys>,
+    #[serde(default)]
+    name: String,
+}
+
+impl WeatherResponse {
+    /// Converts the temperatures of a response fetched in `fetched` units to
+    /// `temperature` units and the wind speed to `wind` units.
+    fn convert(&mut self, fetched: Units, temperature: Units, wind: Units) {
+        let main = &mut self.main;
+        main.temp = utils::convert_temperature(main.temp, fetched, temperature);
+        main.feels_like = main
+            .feels_like
+            .map(|value| utils::convert_temperature(value, fetched, temperature));
+
+        if let Some(speed) = self.wind.as_mut().and_then(|wind| wind.speed.as_mut()) {
+            *speed = utils::convert_speed(*speed, fetched, wind);
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -57,131 +565,1552 @@ struct Weather {
 
 #[derive(Debug, Deserialize)]
 struct MainWeather {
-    temp: f32,
+    temp: f64,
+    feels_like: Option<f64>,
+    humidity: Option<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WindResponse {
+    speed: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ForecastResponse {
+    list: Vec<ForecastInterval>,
+
+    /// The place the forecast is for, as the service names it.
+    #[serde(default)]
+    city: ForecastCity,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ForecastCity {
+    #[serde(default)]
+    name: String,
+
+    #[serde(default)]
+    country: String,
 }
 
 #[derive(Debug, Deserialize)]
-struct CityLocation {
+struct ForecastInterval {
+    main: ForecastMain,
+    weather: Vec<Weather>,
+
+    /// The UTC start of the interval, as `YYYY-MM-DD HH:MM:SS`.
+    dt_txt: String,
+}
+
+impl ForecastInterval {
+    fn date(&self) -> &str {
+        self.dt_txt
+            .split_once(' ')
+            .map_or(self.dt_txt.as_str(), |(date, _)| date)
+    }
+}
+
+impl ForecastResponse {
+    /// Converts the temperatures of a forecast fetched in `fetched` units to `units`.
+    fn convert(&mut self, fetched: Units, units: Units) {
+        for interval in &mut self.list {
+            let main = &mut interval.main;
+            main.temp_min = utils::convert_temperature(main.temp_min, fetched, units);
+            main.temp_max = utils::convert_temperature(main.temp_max, fetched, units);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ForecastMain {
+    temp_min: f64,
+    temp_max: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Sys {
+    country: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Clouds {
+    all: u8,
+}
+
+#[derive(Debug, Deserialize, Getters, Serialize)]
+pub struct CityLocation {
+    #[getset(get = "pub")]
     lat: f64,
+
+    #[getset(get = "pub")]
     lon: f64,
+
+    #[getset(get = "pub")]
     country: String,
+
+    #[getset(get = "pub")]
     state: Option<String>,
+
+    #[getset(get = "pub")]
     name: String,
+
+    /// The name by language code, which geocoding matches usually carry.
+    #[serde(default, skip_serializing)]
+    local_names: HashMap<String, String>,
 }
 
-impl Client<Offline> {
-    pub fn new(config: Config) -> Self {
-        Client::<Offline> {
-            config,
-            client: ClientBuilder::new().build().unwrap(),
-            _state: std::marker::PhantomData,
-        }
-    }
+/// The language codes OpenWeatherMap translates weather descriptions to.
+pub const SUPPORTED_LANGUAGES: [&str; 49] = [
+    "af", "al", "ar", "az", "bg", "ca", "cz", "da", "de", "el", "en", "es", "eu", "fa", "fi", "fr",
+    "gl", "he", "hi", "hr", "hu", "id", "it", "ja", "kr", "la", "lt", "mk", "nl", "no", "pl", "pt",
+    "pt_br", "ro", "ru", "se", "sk", "sl", "sp", "sr", "sv", "th", "tr", "ua", "uk", "vi", "zh_cn",
+    "zh_tw", "zu",
+];
 
-    pub async fn connect(self) -> Result<Client<Connected>, Error> {
-        let client = Client::<Connected> {
-            config: self.config,
-            client: self.client,
-            _state: std::marker::PhantomData,
-        };
+pub fn is_supported_language(lang: &str) -> bool {
+    SUPPORTED_LANGUAGES.contains(&lang.to_lowercase().as_str())
+}
 
-        client
-            .get_city_locations("London")
-            .await
-            .context("Failed to connect to the weather service.")?;
+/// The ISO 639-1 code of an OpenWeatherMap language code, as geocoding matches key
+/// their `local_names` by: a few codes differ, and `la` and `se` even mean Latin and
+/// Northern Sami there. Region suffixes like the `_cn` of `zh_cn` are dropped.
+fn iso_language(lang: &str) -> String {
+    let lang = lang.to_lowercase();
+    let lang = lang.split('_').next().unwrap_or_default();
 
-        Ok(client)
+    match lang {
+        "al" => "sq",
+        "cz" => "cs",
+        "kr" => "ko",
+        "la" => "lv",
+        "se" => "sv",
+        "sp" => "es",
+        "ua" => "uk",
+        lang => lang,
     }
+    .to_owned()
 }
 
-impl Client<Connected> {
-    pub async fn get_weather(&self, city: &str) -> Result<Vec<CityWeather>, Error> {
-        let locations = self.get_city_locations(city).await?;
-        let mut weathers = Vec::new();
+impl CityLocation {
+    /// Replaces the name by the one in `lang`, an OpenWeatherMap language code, if the
+    /// match has one.
+    fn localize(&mut self, lang: &str) {
+        if let Some(name) = self.local_names.remove(&iso_language(lang)) {
+            self.name = name;
+        }
+    }
+}
 
-        for weather in locations
-            .into_iter()
-            .sorted_by(|a, b| Ord::cmp(&b.country, &a.country))
-            .sorted_by(|a, b| Ord::cmp(&b.state, &a.state))
-            .dedup_by(|x, y| x.country == y.country && x.state == y.state)
-        {
-            if let Some(val) = self
-                .get_city_weather(weather.lat, weather.lon, &weather.name)
-                .await
-            {
-                weathers.push(CityWeather {
-                    weather: val.0,
-                    temperature: val.1,
-                    country: weather.country,
-                    city_name: weather.name,
-                    state: weather.state,
-                });
-            }
+impl Config {
+    /// The units to request the readings of a result in `country` in, or of one whose
+    /// country is only known from the response if `None`. Readings shown in units that
+    /// are not known up front, or that differ per field, are fetched in standard units
+    /// and converted.
+    fn fetched_units(&self, country: Option<&str>) -> Units {
+        match country {
+            _ if self.temperature_units.is_some() || self.wind_units.is_some() => Units::Standard,
+            Some(country) => self.units.resolve(country),
+            None if self.units == Units::Auto => Units::Standard,
+            None => self.units,
         }
+    }
+
+    /// The units temperatures and wind speeds of a result in `country` are shown in.
+    fn shown_units(&self, country: &str) -> (Units, Units) {
+        let shown = |units: Option<Units>| units.unwrap_or(self.units).resolve(country);
 
-        Ok(weathers)
+        (shown(self.temperature_units), shown(self.wind_units))
     }
 
-    async fn get_city_weather(&self, lat: f64, lon: f64, city: &str) -> Option<(String, f32)> {
-        let response: Result<WeatherResponse, Error> = self
-            .get_response(
-                "https://api.openweathermap.org/data/2.5/weather",
-                &[
-                    ("lat", lat.to_string().as_str()),
-                    ("lon", lon.to_string().as_str()),
-                    ("units", "metric"),
-                    ("lang", self.config.lang.as_str()),
-                ],
-                "city_weather",
-            )
-            .await;
+    /// OpenWeatherMap keys are 32 hexadecimal characters. Formats could change, so a
+    /// mismatch is only reported rather than rejected.
+    fn has_well_formed_api_key(&self) -> bool {
+        self.api_key.len() == 32 && self.api_key.chars().all(|c| c.is_ascii_hexdigit())
+    }
+}
 
-        if let Err(e) = response {
-            warn!("failed to get weather for {} city: {}", city, e);
-            return None;
-        }
+/// Fails when `locations` span more than `max_countries` countries.
+fn check_ambiguity(
+    city: &str,
+    locations: &[CityLocation],
+    max_countries: usize,
+) -> Result<(), WeatherError> {
+    let countries: Vec<&str> = locations
+        .iter()
+        .map(|location| location.country.as_str())
+        .sorted()
+        .dedup()
+        .collect();
 
-        let response = response.unwrap();
+    if countries.len() > max_countries {
+        return Err(WeatherError::Ambiguous(format!(
+            "\"{}\" matches places in {} countries ({}), add a country code to pick one, e.g. \"{},{}\".",
+            city,
+            countries.len(),
+            countries.join(", "),
+            city,
+            countries[0]
+        )));
+    }
 
-        response
-            .weather
-            .first()
-            .map(|v| (v.description.to_string(), response.main.temp))
+    Ok(())
+}
+
+/// Matches with the same name and region closer than this are taken for one place
+/// that the geocoding service lists more than once.
+const SAME_PLACE_DISTANCE_KM: f64 = 10.0;
+
+/// Drops the locations that repeat an earlier one, keeping the first of each. With
+/// `unique_regions` any two in the same country and state repeat each other, otherwise
+/// only [`is_same_place`] ones do, so distinct towns sharing a name and state are kept.
+fn dedup_locations(locations: &mut Vec<CityLocation>, unique_regions: bool) {
+    let mut kept: Vec<CityLocation> = Vec::with_capacity(locations.len());
+
+    for location in locations.drain(..) {
+        let repeated = kept.iter().any(|other| {
+            other.country == location.country
+                && other.state == location.state
+                && (unique_regions || is_same_place(other, &location))
+        });
+
+        if !repeated {
+            kept.push(location);
+        }
     }
 
-    async fn get_city_locations(&self, city: &str) -> Result<Vec<CityLocation>, Error> {
-        let locations: Vec<CityLocation> = self
-            .get_response(
-                "http://api.openweathermap.org/geo/1.0/direct",
-                &[("q", city), ("limit", "100")],
-                "city_location",
-            )
-            .await?;
-        Ok(locations)
+    *locations = kept;
+}
+
+fn is_same_place(a: &CityLocation, b: &CityLocation) -> bool {
+    a.name == b.name && distance_km(a, b) < SAME_PLACE_DISTANCE_KM
+}
+
+/// The great-circle distance between two locations, by the haversine formula.
+fn distance_km(a: &CityLocation, b: &CityLocation) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6371.0;
+
+    let (lat_a, lat_b) = (a.lat.to_radians(), b.lat.to_radians());
+    let half_lat = (lat_b - lat_a) / 2.0;
+    let half_lon = (b.lon - a.lon).to_radians() / 2.0;
+    let h = half_lat.sin().powi(2) + lat_a.cos() * lat_b.cos() * half_lon.sin().powi(2);
+
+    2.0 * EARTH_RADIUS_KM * h.sqrt().asin()
+}
+
+const MAX_REDIRECTS: usize = 10;
+
+/// The wait the `Retry-After` header of `response` asks for.
+fn parse_retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(reqwest::header::RETRY_AFTER)?;
+
+    retry_after_wait(value.to_str().ok()?, SystemTime::now())
+}
+
+/// The wait a `Retry-After` value asks for at `now`, given either in seconds or as the
+/// HTTP date to retry after; a date that has passed asks for no wait.
+fn retry_after_wait(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+
+    match value.parse() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => {
+            utils::parse_http_date(value).map(|date| date.duration_since(now).unwrap_or_default())
+        }
     }
+}
 
-    async fn get_response<T: DeserializeOwned, U: Serialize + Sized>(
-        &self,
-        url: impl IntoUrl,
-        query: &U,
-        identifier: &'static str,
-    ) -> Result<T, Error> {
-        let _timing = Timing::new(identifier);
-        let request = self
-            .client
-            .get(url)
-            .query(query)
-            .query(&[("appid", &self.config.api_key)]);
-
-        let result = request.send().await?;
-
-        if result.status() == StatusCode::UNAUTHORIZED {
-            bail!("Invalid API key for weather service. Please check the configuration.")
-        };
+/// What the request to `url` asks about, for messages: the city or zip code it looks
+/// up, or the coordinates it gets the weather at.
+fn request_subject(url: &Url, identifier: &str) -> String {
+    let parameter = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
 
-        Ok(result
-            .json::<T>()
-            .await
-            .context("Failed to parse JSON response.")?)
+    match (
+        parameter("q"),
+        parameter("zip"),
+        parameter("lat"),
+        parameter("lon"),
+    ) {
+        (Some(city), ..) => format!("\"{}\"", city),
+        (None, Some(zip), ..) => format!("zip code {}", zip),
+        (None, None, Some(lat), Some(lon)) => format!("{},{}", lat, lon),
+        _ => format!("the {} request", identifier),
+    }
+}
+
+/// The number of geocoding matches requested when all of them are needed.
+const MAX_GEOCODING_MATCHES: usize = 100;
+
+/// Follows redirects like reqwest's default policy, but logs them. The API key is sent
+/// as the `appid` query parameter, so a redirect whose target drops the query would
+/// otherwise only show up as a confusing "invalid API key" error.
+fn redirect_policy() -> Policy {
+    Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+
+        let url = attempt.url();
+        let target = format!("{}{}", url.host_str().unwrap_or_default(), url.path());
+
+        if url.query_pairs().any(|(key, _)| key == "appid") {
+            warn!("following redirect to {}", target);
+        } else {
+            warn!("following redirect to {}, which drops the API key", target);
+        }
+
+        attempt.follow()
+    })
+}
+
+fn http_client(config: &Config) -> reqwest::Result<HttpClient> {
+    ClientBuilder::new()
+        .redirect(redirect_policy())
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .build()
+}
+
+impl<T> Client<T> {
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.config.base_url.trim_end_matches('/'), path)
+    }
+
+    /// The URL a request with `query` goes to, leaving out the API key.
+    fn request_url<U: Serialize + Sized>(
+        &self,
+        url: impl IntoUrl,
+        query: &U,
+    ) -> Result<Url, WeatherError> {
+        Ok(self.client.get(url).query(query).build()?.url().clone())
+    }
+
+    /// The URL of a request with its coordinates rounded to the
+    /// [`Config::cache_coordinate_precision`].
+    fn cache_key(&self, url: &Url) -> String {
+        let precision = self.config.cache_coordinate_precision;
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(name, value)| {
+                let value = match (name.as_ref(), value.parse::<f64>()) {
+                    ("lat" | "lon", Ok(coordinate)) => format!("{:.*}", precision, coordinate),
+                    _ => value.into_owned(),
+                };
+                (name.into_owned(), value)
+            })
+            .collect();
+
+        let mut key = url.clone();
+        key.query_pairs_mut().clear().extend_pairs(pairs);
+        key.to_string()
+    }
+
+    fn coordinates_query(&self, lat: f64, lon: f64, units: Units) -> [(&'static str, String); 4] {
+        [
+            ("lat", lat.to_string()),
+            ("lon", lon.to_string()),
+            ("units", units.query_value().to_owned()),
+            ("lang", self.config.lang.clone()),
+        ]
+    }
+
+    /// The matches the configured country is picked from are geocoded without a smaller
+    /// `limit`, since that could drop the ones in the country.
+    fn geocoding_limit(&self, limit: usize) -> usize {
+        match self.config.country {
+            Some(_) => MAX_GEOCODING_MATCHES,
+            None => limit,
+        }
+    }
+}
+
+fn geocoding_query(city: &str, limit: usize) -> [(&'static str, String); 2] {
+    [("q", city.to_owned()), ("limit", limit.to_string())]
+}
+
+impl Client<Offline> {
+    pub fn new(config: Config) -> Result<Self, WeatherError> {
+        if !config.has_well_formed_api_key() {
+            warn!("API key does not look like an OpenWeatherMap key (32 hex characters)");
+        }
+
+        let mut client = Client::<Offline> {
+            client: http_client(&config).map_err(|error| {
+                WeatherError::network("Failed to create the HTTP client.", error)
+            })?,
+            cache: Cache::new(
+                Duration::from_secs(config.cache_ttl_secs),
+                config.cache_dir.clone(),
+            ),
+            fixtures: None,
+            retries: AtomicU32::new(0),
+            config,
+            _state: std::marker::PhantomData,
+        };
+
+        if let Some(path) = client.config.seed_cache_from.clone() {
+            client.seed_cache(&path)?;
+        }
+
+        Ok(client)
+    }
+
+    /// Caches the weather of every result in `path` at its coordinates, in the units it
+    /// would be fetched in, and the results as the geocoding matches of their city names.
+    /// Forecasts are not seeded.
+    fn seed_cache(&mut self, path: &Path) -> Result<(), WeatherError> {
+        let seeds = Seed::load(path)?;
+
+        for seed in &seeds {
+            let mut units = vec![
+                self.config.fetched_units(Some(&seed.country)),
+                self.config.fetched_units(None),
+            ];
+            units.dedup();
+
+            for units in units {
+                let query = self.coordinates_query(seed.lat, seed.lon, units);
+                let url = self.request_url(self.endpoint("data/2.5/weather"), &query)?;
+                self.cache
+                    .seed(self.cache_key(&url), seed.weather_body(units));
+            }
+        }
+
+        let matches = seeds
+            .iter()
+            .into_group_map_by(|seed| seed.city_name.as_str());
+        for (city, seeds) in matches {
+            for limit in [1, MAX_GEOCODING_MATCHES] {
+                let limit = self.geocoding_limit(limit);
+                let query = geocoding_query(city, limit);
+                let url = self.request_url(self.endpoint("geo/1.0/direct"), &query)?;
+                let locations = seeds.iter().take(limit).map(|seed| seed.location());
+                let body = serde_json::Value::from_iter(locations).to_string();
+                self.cache.seed(self.cache_key(&url), body.into_bytes());
+            }
+        }
+
+        debug!(
+            "seeded the cache with {} results from {}",
+            seeds.len(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    pub async fn connect(self) -> Result<Client<Connected>, WeatherError> {
+        let client = Client::<Connected> {
+            config: self.config,
+            client: self.client,
+            cache: self.cache,
+            fixtures: None,
+            retries: self.retries,
+            _state: std::marker::PhantomData,
+        };
+
+        if client.config.offline || client.config.skip_connect_check {
+            return Ok(client);
+        }
+
+        // A single weather request checks the key and that the service is reachable,
+        // without spending a geocoding call. A rejected key and a rate limit are passed
+        // on as they are, so that they are not mistaken for an unreachable service.
+        match client.get_city_weather(0.0, 0.0, Units::Metric).await {
+            Ok(_) => Ok(client),
+            Err(error @ (WeatherError::InvalidApiKey | WeatherError::RateLimited { .. })) => {
+                Err(error)
+            }
+            Err(error) => Err(WeatherError::network(
+                "Failed to connect to the weather service.",
+                error,
+            )),
+        }
+    }
+}
+
+impl Client<Connected> {
+    /// A client that answers every request from the fixtures file at `path` instead of
+    /// the weather service, for tests and demos. The file maps city names to canned
+    /// responses: `{ "London": { "geocode": [..], "weather": {..}, "forecast": {..} } }`,
+    /// where `geocode` is the list of matches and the others are shared by all of them.
+    pub fn new_mock(config: Config, path: impl AsRef<Path>) -> Result<Self, WeatherError> {
+        Ok(Client::<Connected> {
+            client: http_client(&config).map_err(|error| {
+                WeatherError::network("Failed to create the HTTP client.", error)
+            })?,
+            cache: Cache::new(Duration::ZERO, None),
+            fixtures: Some(Fixtures::load(path.as_ref())?),
+            retries: AtomicU32::new(0),
+            config,
+            _state: std::marker::PhantomData,
+        })
+    }
+
+    pub async fn get_weather(&self, city: &str) -> Result<Vec<CityWeather>, WeatherError> {
+        if self.config.first_match {
+            return Ok(self
+                .get_weather_best_match(city)
+                .await?
+                .into_iter()
+                .collect());
+        }
+
+        let locations = self.get_locations(city).await?;
+
+        Ok(self
+            .for_each_location(locations, |location| self.get_location_weather(location))
+            .await)
+    }
+
+    /// Returns the daily forecast for every location `city` resolves to, or only for the
+    /// most relevant one with `first_match`.
+    pub async fn get_forecast(&self, city: &str) -> Result<Vec<CityForecast>, WeatherError> {
+        let locations = if self.config.first_match {
+            self.get_city_locations(city, 1)
+                .await?
+                .into_iter()
+                .take(1)
+                .collect()
+        } else {
+            self.get_locations(city).await?
+        };
+
+        Ok(self
+            .for_each_location(locations, |location| self.get_location_forecast(location))
+            .await)
+    }
+
+    /// Runs `fetch` for every location, up to `concurrency` at a time, keeping the
+    /// results in the order of `locations` and skipping locations without one.
+    async fn for_each_location<T, F, Fut>(&self, locations: Vec<CityLocation>, fetch: F) -> Vec<T>
+    where
+        F: Fn(CityLocation) -> Fut,
+        Fut: Future<Output = Option<T>>,
+    {
+        // The requests complete out of order, so each result keeps the position of its
+        // location to restore the order afterwards.
+        let mut results: Vec<(usize, T)> = stream::iter(locations.into_iter().enumerate())
+            .map(|(position, location)| {
+                let result = fetch(location);
+                async move { result.await.map(|result| (position, result)) }
+            })
+            .buffer_unordered(self.config.concurrency.max(1))
+            .filter_map(future::ready)
+            .collect()
+            .await;
+        results.sort_by_key(|(position, _)| *position);
+
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Resolves `city` to the locations weather would be fetched for, without fetching it.
+    pub async fn get_locations(&self, city: &str) -> Result<Vec<CityLocation>, WeatherError> {
+        let mut locations: Vec<CityLocation> = self
+            .get_city_locations(city, MAX_GEOCODING_MATCHES)
+            .await?
+            .into_iter()
+            .sorted_by(|a, b| Ord::cmp(&b.country, &a.country))
+            .sorted_by(|a, b| Ord::cmp(&b.state, &a.state))
+            .collect();
+        dedup_locations(&mut locations, self.config.unique_regions);
+
+        if let Some(max_countries) = self.config.max_countries {
+            check_ambiguity(city, &locations, max_countries)?;
+        }
+
+        // The dedup needs every match, so the locations are limited here rather than
+        // in the geocoding query.
+        if let Some(limit) = self.config.limit.filter(|limit| *limit > 0) {
+            locations.truncate(limit);
+        }
+
+        Ok(locations)
+    }
+
+    /// Returns the weather for the location the geocoding service ranks as the most
+    /// relevant match for `city`, if any.
+    pub async fn get_weather_best_match(
+        &self,
+        city: &str,
+    ) -> Result<Option<CityWeather>, WeatherError> {
+        let location = self.get_city_locations(city, 1).await?.into_iter().next();
+
+        match location {
+            Some(location) => Ok(self.get_location_weather(location).await),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the weather at the given coordinates, named after the place the weather
+    /// service reports for them. The country is only known from the response, so
+    /// [`Units::Auto`] fetches standard units and converts them once it is.
+    pub async fn get_weather_at(
+        &self,
+        lat: f64,
+        lon: f64,
+    ) -> Result<Option<CityWeather>, WeatherError> {
+        let fetched = self.config.fetched_units(None);
+        let mut response = self.get_city_weather(lat, lon, fetched).await?;
+        let city_name = std::mem::take(&mut response.name);
+        let country = response
+            .sys
+            .take()
+            .and_then(|sys| sys.country)
+            .unwrap_or_default();
+
+        Ok(CityWeather::new(
+            response,
+            fetched,
+            &self.config,
+            city_name,
+            country,
+            None,
+        ))
+    }
+
+    /// Returns the weather at the place a postal code such as `90210,US` belongs to; the
+    /// country part defaults to the configured country.
+    pub async fn get_weather_by_zip(&self, zip: &str) -> Result<Option<CityWeather>, WeatherError> {
+        let location = self.get_zip_location(zip).await?;
+
+        Ok(self.get_location_weather(location).await)
+    }
+
+    pub async fn get_forecast_by_zip(
+        &self,
+        zip: &str,
+    ) -> Result<Option<CityForecast>, WeatherError> {
+        let location = self.get_zip_location(zip).await?;
+
+        Ok(self.get_location_forecast(location).await)
+    }
+
+    /// Returns the daily forecast at the given coordinates, named after the place the
+    /// forecast reports for them; auto units are resolved like [`Self::get_weather_at`].
+    pub async fn get_forecast_at(&self, lat: f64, lon: f64) -> Result<CityForecast, WeatherError> {
+        let fetched = self.config.fetched_units(None);
+        let mut response = self.get_city_forecast(lat, lon, fetched).await?;
+
+        let (units, _) = self.config.shown_units(&response.city.country);
+        response.convert(fetched, units);
+
+        Ok(CityForecast {
+            city_name: response.city.name,
+            country: response.city.country,
+            state: None,
+            units,
+            days: DailyForecast::from_intervals(response.list),
+        })
+    }
+
+    async fn get_location_weather(&self, location: CityLocation) -> Option<CityWeather> {
+        let fetched = self.config.fetched_units(Some(&location.country));
+
+        let response = self
+            .get_city_weather(location.lat, location.lon, fetched)
+            .await
+            .inspect_err(|e| warn!("failed to get weather for {} city: {}", location.name, e))
+            .ok()?;
+
+        CityWeather::new(
+            response,
+            fetched,
+            &self.config,
+            location.name,
+            location.country,
+            location.state,
+        )
+    }
+
+    async fn get_location_forecast(&self, location: CityLocation) -> Option<CityForecast> {
+        let fetched = self.config.fetched_units(Some(&location.country));
+
+        let mut response = self
+            .get_city_forecast(location.lat, location.lon, fetched)
+            .await
+            .inspect_err(|e| warn!("failed to get forecast for {} city: {}", location.name, e))
+            .ok()?;
+
+        let (units, _) = self.config.shown_units(&location.country);
+        response.convert(fetched, units);
+
+        Some(CityForecast {
+            city_name: location.name,
+            country: location.country,
+            state: location.state,
+            units,
+            days: DailyForecast::from_intervals(response.list),
+        })
+    }
+
+    async fn get_city_forecast(
+        &self,
+        lat: f64,
+        lon: f64,
+        units: Units,
+    ) -> Result<ForecastResponse, WeatherError> {
+        self.get_response(
+            self.endpoint("data/2.5/forecast"),
+            &self.coordinates_query(lat, lon, units),
+            "city_forecast",
+            None,
+        )
+        .await
+    }
+
+    async fn get_city_weather(
+        &self,
+        lat: f64,
+        lon: f64,
+        units: Units,
+    ) -> Result<WeatherResponse, WeatherError> {
+        self.get_response(
+            self.endpoint("data/2.5/weather"),
+            &self.coordinates_query(lat, lon, units),
+            "city_weather",
+            self.config.replay_weather.as_ref(),
+        )
+        .await
+    }
+
+    /// Geocodes `city` to at most `limit` matches in the configured country, if any.
+    async fn get_city_locations(
+        &self,
+        city: &str,
+        limit: usize,
+    ) -> Result<Vec<CityLocation>, WeatherError> {
+        let response: GeocodingResponse = self
+            .get_response(
+                self.endpoint("geo/1.0/direct"),
+                &geocoding_query(city, self.geocoding_limit(limit)),
+                "city_location",
+                self.config.replay_geocode.as_ref(),
+            )
+            .await?;
+
+        let mut locations = response.into_locations()?;
+
+        if let Some(country) = &self.config.country {
+            locations.retain(|location| location.country.eq_ignore_ascii_case(country));
+        }
+
+        // `lang` only translates weather descriptions, so names are localized here from
+        // the geocoding matches.
+        for location in &mut locations {
+            location.localize(&self.config.lang);
+        }
+
+        Ok(locations)
+    }
+
+    async fn get_zip_location(&self, zip: &str) -> Result<CityLocation, WeatherError> {
+        let zip = zip.trim();
+        let zip = match (zip.contains(','), &self.config.country) {
+            (true, _) => zip.to_owned(),
+            (false, Some(country)) => format!("{},{}", zip, country),
+            (false, None) => {
+                return Err(WeatherError::Config {
+                    message: format!(
+                        "The ZIP code \"{}\" needs a country code, e.g. \"{},US\", or a configured country.",
+                        zip, zip
+                    ),
+                    source: None,
+                })
+            }
+        };
+
+        let response: ZipResponse = self
+            .get_response(
+                self.endpoint("geo/1.0/zip"),
+                &[("zip", zip.as_str())],
+                "zip_location",
+                self.config.replay_geocode.as_ref(),
+            )
+            .await?;
+
+        match response {
+            ZipResponse::Location(location) => Ok(location),
+            ZipResponse::Error { message } => Err(WeatherError::NoResults(format!(
+                "ZIP code lookup failed for \"{}\": {}",
+                zip, message
+            ))),
+        }
+    }
+
+    /// Sends the request, or reads the body from `replay`, the fixtures or the cache when
+    /// given, and parses it the same way in all cases. Bodies that parse are cached; the cache is
+    /// checked before the offline guard so offline mode can still serve cached data.
+    async fn get_response<T: DeserializeOwned, U: Serialize + Sized>(
+        &self,
+        url: impl IntoUrl,
+        query: &U,
+        identifier: &'static str,
+        replay: Option<&PathBuf>,
+    ) -> Result<T, WeatherError> {
+        // Fresh network bodies carry the cache key to store them under once they parse;
+        // the key leaves out the API key, which `send_request` adds.
+        let (body, key) = match replay {
+            Some(path) => {
+                let body = fs::read(path).map_err(|error| {
+                    WeatherError::config(
+                        format!("Failed to read the replayed response {}.", path.display()),
+                        error,
+                    )
+                })?;
+                (body, None)
+            }
+            None => {
+                let url = url.into_url()?;
+                let request_url = self.request_url(url.clone(), query)?;
+                let key = self.cache_key(&request_url);
+
+                if let Some(fixtures) = &self.fixtures {
+                    (fixtures.response(identifier, &request_url)?, None)
+                } else if let Some(body) = self.cache.get(&key) {
+                    debug!("{}: cache hit", identifier);
+                    (body, None)
+                } else {
+                    debug!("{}: cache miss", identifier);
+                    (self.send_request(url, query, identifier).await?, Some(key))
+                }
+            }
+        };
+
+        let response = serde_json::from_slice::<T>(&body).map_err(WeatherError::Parse)?;
+
+        if let Some(key) = key {
+            self.cache.insert(&key, &body);
+        }
+
+        Ok(response)
+    }
+
+    async fn send_request<U: Serialize + Sized>(
+        &self,
+        url: impl IntoUrl,
+        query: &U,
+        identifier: &'static str,
+    ) -> Result<Vec<u8>, WeatherError> {
+        let url = url.into_url()?;
+
+        if self.config.offline {
+            let request = self.client.get(url).query(query).build()?;
+
+            return Err(WeatherError::Network {
+                message: format!(
+                    "Offline mode: no cached data for {}.",
+                    request_subject(request.url(), identifier)
+                ),
+                source: None,
+            });
+        }
+
+        let attempts = self.config.max_retries.saturating_add(1);
+        let max_retry_after = Duration::from_secs(self.config.max_retry_after_secs);
+
+        // Rate limiting, server errors and connection failures may go away on their own
+        // and are retried with exponential backoff; other failures are final.
+        let mut attempt = 1;
+        loop {
+            let request = self
+                .client
+                .get(url.clone())
+                .query(query)
+                .query(&[("appid", &self.config.api_key)]);
+
+            // Every attempt is timed on its own, leaving out the backoff before the next.
+            let timing = Timing::new(identifier);
+
+            // On rate limiting, `rate_limit` holds the wait the service asked for, if any.
+            let (failure, rate_limit) = match request.send().await {
+                Ok(result) if result.status() == StatusCode::UNAUTHORIZED => {
+                    return Err(WeatherError::InvalidApiKey);
+                }
+                Ok(result) if result.status() == StatusCode::TOO_MANY_REQUESTS => (
+                    format!("status {}", result.status()),
+                    Some(parse_retry_after(&result)),
+                ),
+                Ok(result) if result.status().is_server_error() => {
+                    (format!("status {}", result.status()), None)
+                }
+                Ok(result) => return Ok(result.bytes().await?.to_vec()),
+                Err(error) if error.is_connect() || error.is_timeout() => (error.to_string(), None),
+                Err(error) => return Err(error.into()),
+            };
+            drop(timing);
+
+            let too_long = rate_limit
+                .flatten()
+                .is_some_and(|wait| wait > max_retry_after);
+            if attempt >= attempts || too_long || !self.take_retry() {
+                if let Some(retry_after) = rate_limit {
+                    return Err(WeatherError::RateLimited { retry_after });
+                }
+
+                return Err(WeatherError::Network {
+                    message: format!(
+                        "The {} request failed after {} attempts: {}",
+                        identifier, attempt, failure
+                    ),
+                    source: None,
+                });
+            }
+
+            // The service tells how long a rate limit lasts, which beats guessing.
+            let backoff = rate_limit.flatten().unwrap_or(Duration::from_millis(
+                self.config
+                    .base_backoff_ms
+                    .saturating_mul(2u64.saturating_pow(attempt - 1)),
+            ));
+            warn!(
+                "{} request failed (attempt {} of {}): {}, retrying in {:?}",
+                identifier, attempt, attempts, failure, backoff
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+
+    /// Spends one retry of the run-wide [`Config::max_total_retries`], unless it is used up.
+    fn take_retry(&self) -> bool {
+        let Some(budget) = self.config.max_total_retries else {
+            return true;
+        };
+
+        let spent = self.retries.fetch_add(1, Ordering::Relaxed);
+        if spent == budget {
+            warn!(
+                "all {} retries of this run are spent, failing requests without retrying",
+                budget
+            );
+        }
+
+        spent < budget
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs, process,
+        sync::atomic::AtomicU32,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use super::{
+        check_ambiguity, dedup_locations, geocoding_query, http_client, is_supported_language,
+        redirect_policy, retry_after_wait, Cache, CityLocation, CityWeather, Client, Config,
+        Connected, DailyForecast, ForecastResponse, GeocodingResponse, Units, WeatherError,
+    };
+
+    fn config_with_key(api_key: &str) -> Config {
+        Config {
+            api_key: api_key.to_owned(),
+            lang: "en".to_owned(),
+            units: Units::Metric,
+            temperature_units: None,
+            wind_units: None,
+            first_match: false,
+            max_countries: None,
+            offline: false,
+            replay_weather: None,
+            replay_geocode: None,
+            concurrency: 8,
+            timeout_secs: 10,
+            connect_timeout_secs: 5,
+            max_retries: 2,
+            max_total_retries: None,
+            base_backoff_ms: 500,
+            max_retry_after_secs: 60,
+            base_url: "https://api.openweathermap.org".to_owned(),
+            limit: None,
+            country: None,
+            skip_connect_check: false,
+            unique_regions: false,
+            cache_ttl_secs: 0,
+            cache_dir: None,
+            cache_coordinate_precision: 4,
+            seed_cache_from: None,
+        }
+    }
+
+    #[test]
+    fn api_key_format() {
+        assert!(config_with_key("0123456789abcdef0123456789ABCDEF").has_well_formed_api_key());
+        assert!(!config_with_key("<key>").has_well_formed_api_key());
+        assert!(!config_with_key("0123456789abcdef0123456789abcde").has_well_formed_api_key());
+        assert!(!config_with_key("0123456789abcdef0123456789abcdeg").has_well_formed_api_key());
+    }
+
+    #[test]
+    fn auto_units_resolve_by_country() {
+        assert_eq!(Units::Auto.resolve("US"), Units::Imperial);
+        assert_eq!(Units::Auto.resolve("LR"), Units::Imperial);
+        assert_eq!(Units::Auto.resolve("GB"), Units::Metric);
+        assert_eq!(Units::Metric.resolve("US"), Units::Metric);
+        assert_eq!(Units::Imperial.resolve("DE"), Units::Imperial);
+        assert_eq!(Units::Standard.resolve("US"), Units::Standard);
+    }
+
+    #[tokio::test]
+    async fn auto_units_at_coordinates_follow_the_reported_country() {
+        let path = env::temp_dir().join(format!("city_weather_auto_{}.json", process::id()));
+        fs::write(
+            &path,
+            r#"{ "Boston": {
+                "geocode": [{ "name": "Boston", "lat": 42.36, "lon": -71.06, "country": "US" }],
+                "weather": { "weather": [{ "description": "clear sky" }], "main": { "temp": 285.15 }, "wind": { "speed": 10.0 }, "sys": { "country": "US" }, "name": "Boston" },
+                "forecast": { "list": [{ "main": { "temp_min": 273.15, "temp_max": 283.15 }, "weather": [{ "description": "rain" }], "dt_txt": "2026-10-14 12:00:00" }], "city": { "name": "Boston", "country": "US" } }
+            } }"#,
+        )
+        .unwrap();
+
+        let config = |wind_units| {
+            let mut config = config_with_key("key");
+            config.set_units(Units::Auto);
+            config.set_wind_units(wind_units);
+            config
+        };
+        let client = Client::new_mock(config(None), &path).unwrap();
+
+        let weather = client.get_weather_at(42.36, -71.06).await.unwrap().unwrap();
+        assert_eq!(*weather.units(), Units::Imperial);
+        assert_eq!(*weather.temperature(), 53.6);
+        assert_eq!(*weather.wind_speed(), Some(22.37));
+
+        let forecast = client.get_forecast_at(42.36, -71.06).await.unwrap();
+        assert_eq!(*forecast.units(), Units::Imperial);
+        assert_eq!(forecast.days()[0].min_temperature, 32.0);
+        assert_eq!(forecast.days()[0].max_temperature, 50.0);
+
+        let client = Client::new_mock(config(Some(Units::Metric)), &path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let weather = client.get_weather_at(42.36, -71.06).await.unwrap().unwrap();
+        assert_eq!(*weather.units(), Units::Imperial);
+        assert_eq!(*weather.temperature(), 53.6);
+        assert_eq!(*weather.wind_units(), Units::Metric);
+        assert_eq!(*weather.wind_speed(), Some(10.0));
+    }
+
+    #[test]
+    fn temperature_serializes_with_unit() {
+        let weather = CityWeather {
+            weather: "clear sky".to_owned(),
+            country: "US".to_owned(),
+            state: None,
+            city_name: "Boston".to_owned(),
+            temperature: -4.5,
+            cloudiness: None,
+            units: Units::Imperial,
+            feels_like: Some(-10.0),
+            humidity: Some(80),
+            wind_speed: Some(12.5),
+            wind_units: Units::Metric,
+        };
+
+        let value = serde_json::to_value(&weather).unwrap();
+        assert_eq!(
+            value["temperature"],
+            serde_json::json!({ "value": -4.5, "unit": "F" })
+        );
+        assert_eq!(
+            value["feels_like"],
+            serde_json::json!({ "value": -10.0, "unit": "F" })
+        );
+        assert_eq!(
+            value["wind"],
+            serde_json::json!({ "speed": 12.5, "unit": "m/s" })
+        );
+        assert_eq!(value["units"], "imperial");
+    }
+
+    fn location(country: &str) -> CityLocation {
+        CityLocation {
+            lat: 0.0,
+            lon: 0.0,
+            country: country.to_owned(),
+            state: None,
+            name: "Springfield".to_owned(),
+            local_names: Default::default(),
+        }
+    }
+
+    #[test]
+    fn language_codes() {
+        assert!(is_supported_language("en"));
+        assert!(is_supported_language("PT_BR"));
+        assert!(!is_supported_language("eng"));
+    }
+
+    #[test]
+    fn place_names_are_localized_by_iso_code() {
+        let localized = |lang: &str| {
+            let mut london = location("GB");
+            london.local_names = [("la", "Londinium"), ("lv", "Londona"), ("zh", "伦敦")]
+                .into_iter()
+                .map(|(lang, name)| (lang.to_owned(), name.to_owned()))
+                .collect();
+            london.localize(lang);
+            london.name
+        };
+
+        assert_eq!(localized("la"), "Londona");
+        assert_eq!(localized("zh_cn"), "伦敦");
+        assert_eq!(localized("kr"), "Springfield");
+    }
+
+    #[test]
+    fn ambiguity_check_counts_countries() {
+        let locations = [
+            location("US"),
+            location("US"),
+            location("AU"),
+            location("CA"),
+        ];
+
+        assert!(check_ambiguity("Springfield", &locations, 3).is_ok());
+        assert!(check_ambiguity("Springfield", &locations[..2], 1).is_ok());
+
+        let error = check_ambiguity("Springfield", &locations, 2).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "\"Springfield\" matches places in 3 countries (AU, CA, US), add a country code to pick one, e.g. \"Springfield,AU\"."
+        );
+    }
+
+    #[test]
+    fn dedup_keeps_distinct_places_in_one_state() {
+        let springfield = |lat, lon| CityLocation {
+            lat,
+            lon,
+            state: Some("Illinois".to_owned()),
+            ..location("US")
+        };
+        let locations = || {
+            vec![
+                springfield(39.80, -89.64),
+                springfield(39.81, -89.65),
+                springfield(41.46, -89.82),
+            ]
+        };
+
+        let mut places = locations();
+        dedup_locations(&mut places, false);
+        assert_eq!(places.len(), 2);
+        assert_eq!(*places[1].lat(), 41.46);
+
+        let mut regions = locations();
+        dedup_locations(&mut regions, true);
+        assert_eq!(regions.len(), 1);
+    }
+
+    #[test]
+    fn geocoding_response_locations() {
+        let response: GeocodingResponse = serde_json::from_str(
+            r#"[{"name": "London", "lat": 51.5, "lon": -0.12, "country": "GB", "state": "England"}]"#,
+        )
+        .unwrap();
+
+        let locations = response.into_locations().unwrap();
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].name(), "London");
+    }
+
+    #[test]
+    fn geocoding_response_error_object() {
+        let response: GeocodingResponse =
+            serde_json::from_str(r#"{"cod": "400", "message": "Nothing to geocode"}"#).unwrap();
+
+        let error = response.into_locations().unwrap_err();
+        assert_eq!(error.to_string(), "Geocoding failed: Nothing to geocode");
+    }
+
+    #[test]
+    fn forecast_intervals_group_by_day() {
+        let response: ForecastResponse = serde_json::from_str(
+            r#"{"list": [
+                {"main": {"temp_min": 8.0, "temp_max": 11.0}, "weather": [{"description": "rain"}], "dt_txt": "2024-06-30 18:00:00"},
+                {"main": {"temp_min": 6.5, "temp_max": 9.0}, "weather": [{"description": "clear sky"}], "dt_txt": "2024-06-30 21:00:00"},
+                {"main": {"temp_min": 5.0, "temp_max": 7.0}, "weather": [{"description": "clear sky"}], "dt_txt": "2024-07-01 00:00:00"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let days = DailyForecast::from_intervals(response.list);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].date(), "2024-06-30");
+        assert_eq!(days[0].weather(), "rain");
+        assert_eq!(*days[0].min_temperature(), 6.5);
+        assert_eq!(*days[0].max_temperature(), 11.0);
+        assert_eq!(*days[0].intervals(), 2);
+        assert_eq!(days[1].date(), "2024-07-01");
+        assert_eq!(*days[1].intervals(), 1);
+    }
+
+    /// An HTTP/1.1 response with `status`, the extra `headers` and `body`.
+    fn response(status: &str, headers: &[&str], body: &str) -> String {
+        let headers: String = headers
+            .iter()
+            .map(|header| format!("{}\r\n", header))
+            .collect();
+
+        format!(
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\n\r\n{}",
+            status,
+            headers,
+            body.len(),
+            body
+        )
+    }
+
+    /// Serves the `responses` in turn, one per connection, returning the base URL of the
+    /// server and a handle that resolves to the request line of every request served.
+    async fn serve(responses: Vec<String>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0; 1024];
+                let read = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..read]);
+                requests.push(request.lines().next().unwrap_or_default().to_owned());
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        (base_url, server)
+    }
+
+    /// A client for `config` that skips the connection check and caches nothing.
+    fn connected_client(config: Config) -> Client<Connected> {
+        Client::<Connected> {
+            client: http_client(&config).unwrap(),
+            config,
+            cache: Cache::new(Duration::ZERO, None),
+            fixtures: None,
+            retries: AtomicU32::new(0),
+            _state: std::marker::PhantomData,
+        }
+    }
+
+    #[tokio::test]
+    async fn redirects_are_followed() {
+        let (base_url, server) = serve(vec![
+            response("301 Moved Permanently", &["Location: /moved?appid=key"], ""),
+            response("200 OK", &[], "[]"),
+        ])
+        .await;
+
+        let client = reqwest::ClientBuilder::new()
+            .redirect(redirect_policy())
+            .build()
+            .unwrap();
+        let response = client
+            .get(format!("{}/geo?appid=key", base_url))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.url().path(), "/moved");
+        assert_eq!(response.text().await.unwrap(), "[]");
+        assert_eq!(
+            server.await.unwrap(),
+            [
+                "GET /geo?appid=key HTTP/1.1",
+                "GET /moved?appid=key HTTP/1.1"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_time_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        // Accept connections but never answer them.
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                streams.push(stream);
+            }
+        });
+
+        let mut config = config_with_key("key");
+        config.set_timeout_secs(1);
+        let client = http_client(&config).unwrap();
+
+        let start = Instant::now();
+        let error = client
+            .get(format!("http://{}/data", address))
+            .send()
+            .await
+            .unwrap_err();
+
+        assert!(error.is_timeout());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() {
+        let (base_url, server) = serve(vec![
+            response("503 Service Unavailable", &[], ""),
+            response("200 OK", &[], "[]"),
+        ])
+        .await;
+
+        let mut config = config_with_key("key");
+        config.set_base_backoff_ms(1);
+        let client = connected_client(config);
+
+        let body = client
+            .send_request(format!("{}/geo", base_url), &[("q", "x")], "test")
+            .await
+            .unwrap();
+        assert_eq!(body, b"[]");
+
+        let requests = server.await.unwrap();
+        assert!(requests
+            .iter()
+            .all(|request| request.starts_with("GET /geo")));
+    }
+
+    #[tokio::test]
+    async fn retries_share_a_budget() {
+        let (base_url, server) = serve(vec![
+            response("503 Service Unavailable", &[], ""),
+            response("200 OK", &[], "[]"),
+            response("503 Service Unavailable", &[], ""),
+        ])
+        .await;
+
+        let mut config = config_with_key("key");
+        config.set_base_backoff_ms(1);
+        config.set_max_total_retries(Some(1));
+        let client = connected_client(config);
+        let url = format!("{}/geo", base_url);
+
+        let body = client.send_request(&url, &[("q", "x")], "test").await;
+        assert_eq!(body.unwrap(), b"[]");
+
+        // The one retry is spent, so the next failure is final despite `max_retries`.
+        let error = client
+            .send_request(&url, &[("q", "x")], "test")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The test request failed after 1 attempts: status 503 Service Unavailable"
+        );
+
+        assert_eq!(server.await.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn cache_keys_round_coordinates() {
+        let mut config = config_with_key("key");
+        config.set_cache_coordinate_precision(2);
+        let client = connected_client(config);
+
+        let url = client
+            .request_url(
+                "https://example.com/data/2.5/weather",
+                &client.coordinates_query(51.50712, -0.12755, Units::Metric),
+            )
+            .unwrap();
+        assert_eq!(
+            client.cache_key(&url),
+            "https://example.com/data/2.5/weather?lat=51.51&lon=-0.13&units=metric&lang=en"
+        );
+
+        let url = client
+            .request_url(
+                "https://example.com/geo/1.0/direct",
+                &geocoding_query("London", 5),
+            )
+            .unwrap();
+        assert_eq!(client.cache_key(&url), url.to_string());
+    }
+
+    #[tokio::test]
+    async fn seeded_results_are_served_offline() {
+        let path = env::temp_dir().join(format!("city_weather_seed_{}.json", process::id()));
+        let seed = r#"{ "lat": 51.5, "lon": -0.12, "weather": "clouds", "country": "GB",
+            "state": null, "city_name": "London", "temperature": { "value": 12.5, "unit": "C" },
+            "feels_like": null, "humidity": 80, "wind": { "speed": 4.0, "unit": "m/s" },
+            "cloudiness": null, "units": "metric" }"#;
+        let seeded_client = |seeds: String| {
+            fs::write(&path, seeds).unwrap();
+            let mut config = config_with_key("key");
+            config.set_offline(true);
+            config.set_units(Units::Imperial);
+            config.set_seed_cache_from(Some(path.clone()));
+            Client::new(config)
+        };
+
+        let client = seeded_client(format!("[{}]", seed))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let weathers = client.get_weather("London").await.unwrap();
+        assert_eq!(weathers.len(), 1);
+        assert_eq!(*weathers[0].temperature(), 54.5);
+        assert_eq!(*weathers[0].wind_speed(), Some(8.95));
+        assert_eq!(*weathers[0].humidity(), Some(80));
+
+        // Points closer than the cache key precision share the seeded weather.
+        let weather = client
+            .get_weather_at(51.50001, -0.12)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(weather.city_name(), "London");
+
+        let error = seeded_client(format!("[{}]", seed.replace(r#""C""#, r#""F""#)))
+            .err()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Invalid result 1 (London) in {}: the temperature unit F does not match the metric units.",
+                path.display()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn offline_errors_name_the_query() {
+        let mut config = config_with_key("key");
+        config.set_offline(true);
+        let client = Client::new(config).unwrap().connect().await.unwrap();
+
+        let error = client.get_weather("London").await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Offline mode: no cached data for \"London\"."
+        );
+
+        let error = client.get_weather_at(51.5, -0.12).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Offline mode: no cached data for 51.5,-0.12."
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_reports_rate_limits() {
+        let rate_limited = response("429 Too Many Requests", &["Retry-After: 120"], "");
+        let (base_url, _server) = serve(vec![rate_limited]).await;
+
+        let mut config = config_with_key("key");
+        config.set_base_url(base_url);
+        let error = Client::new(config).unwrap().connect().await.err().unwrap();
+
+        assert!(matches!(
+            error,
+            WeatherError::RateLimited {
+                retry_after: Some(wait)
+            } if wait == Duration::from_secs(120)
+        ));
+    }
+
+    #[tokio::test]
+    async fn rate_limits_honor_retry_after() {
+        let rate_limited = |retry_after| {
+            response(
+                "429 Too Many Requests",
+                &[&format!("Retry-After: {}", retry_after)],
+                "",
+            )
+        };
+        let (base_url, _server) = serve(vec![
+            rate_limited("1"),
+            response("200 OK", &[], "[]"),
+            rate_limited("1"),
+            rate_limited("Fri, 01 Jan 2100 00:00:00 GMT"),
+        ])
+        .await;
+
+        let mut config = config_with_key("key");
+        config.set_base_backoff_ms(1);
+        let mut client = connected_client(config);
+        let url = format!("{}/geo", base_url);
+
+        let start = Instant::now();
+        let body = client.send_request(&url, &[("q", "x")], "test").await;
+        assert_eq!(body.unwrap(), b"[]");
+        assert!(start.elapsed() >= Duration::from_secs(1));
+
+        client.config.set_max_retries(0);
+        let error = client
+            .send_request(&url, &[("q", "x")], "test")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The weather service rate limit was exceeded, retry after 1s."
+        );
+
+        // A rate limit beyond the cap fails at once, however many retries are left.
+        client.config.set_max_retries(2);
+        client.config.set_max_retry_after_secs(60);
+        let error = client
+            .send_request(&url, &[("q", "x")], "test")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            WeatherError::RateLimited { retry_after: Some(wait) } if wait > Duration::from_secs(60)
+        ));
+    }
+
+    #[test]
+    fn retry_after_in_seconds_and_dates() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777);
+
+        assert_eq!(
+            retry_after_wait(" 120 ", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            retry_after_wait("Sun, 06 Nov 1994 08:50:07 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            retry_after_wait("Sun, 06 Nov 1994 08:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after_wait("soon", now), None);
+    }
+
+    #[tokio::test]
+    async fn requests_go_to_the_base_url() {
+        let body = r#"[{"name": "London", "lat": 51.5, "lon": -0.12, "country": "GB"}]"#;
+        let (base_url, server) = serve(vec![response("200 OK", &[], body)]).await;
+
+        let mut config = config_with_key("key");
+        config.set_base_url(format!("{}/", base_url));
+        let client = connected_client(config);
+
+        let locations = client.get_locations("London").await.unwrap();
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].name(), "London");
+        assert!(server.await.unwrap()[0].starts_with("GET /geo/1.0/direct?"));
     }
 }